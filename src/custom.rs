@@ -0,0 +1,134 @@
+//! Custom fields defined in the settings file on top of the built-in ones.
+//!
+//! Their values are stored on `Entry::custom` by name. A field removed from
+//! the settings keeps its stored values, which are still shown and searched.
+
+extern crate regex;
+extern crate serde;
+
+use self::regex::Regex;
+use self::serde::de::{Deserialize, Deserializer, Error};
+
+/// Names used by the built-in fields of `Entry` and the dialog, and by the
+/// qualifiers of the `/ip-list` query, which are matched before custom field
+/// names.
+const RESERVED: &[&str] = &[
+    "schema_version",
+    "revision",
+    "modified",
+    "ip",
+    "dns_names",
+    "status",
+    "open_ports",
+    "description",
+    "owner",
+    "tags",
+    "lease_expires",
+    "macs",
+    "hostname",
+    "model",
+    "location",
+    "details",
+    "domain",
+    "dns",
+    "name",
+    "port",
+    "desc",
+    "tag",
+    "mac",
+    "sort",
+];
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Text,
+    /// One of `options`.
+    Select,
+    /// Text which may span several lines.
+    Textarea,
+}
+
+impl Default for FieldType {
+    fn default() -> FieldType {
+        FieldType::Text
+    }
+}
+
+/// A regular expression a value has to match as a whole.
+#[derive(Clone, Debug)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn is_match(&self, value: &str) -> bool {
+        self.0.is_match(value)
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Pattern, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&format!("^(?:{})$", pattern))
+            .map(Pattern)
+            .map_err(D::Error::custom)
+    }
+}
+
+/// A `[[custom_fields]]` table of the settings file.
+#[derive(Deserialize, Clone, Debug)]
+pub struct CustomField {
+    pub name: String,
+    pub label: String,
+    #[serde(rename = "type", default)]
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub options: Vec<String>,
+    pub pattern: Option<Pattern>,
+}
+
+impl CustomField {
+    /// Checks a value typed in the dialog, returning the reason it's rejected.
+    pub fn validate(&self, value: &str) -> Result<(), String> {
+        if self.field_type == FieldType::Select && !self.options.iter().any(|o| o == value) {
+            return Err(format!(
+                "{}: {} 중 하나여야 합니다.",
+                self.label,
+                self.options.join(", ")
+            ));
+        }
+        if let Some(ref pattern) = self.pattern {
+            if !pattern.is_match(value) {
+                return Err(format!("{}: 형식이 올바르지 않습니다: {}", self.label, value));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks the fields of the settings file, since their names become keys of
+/// the stored entries and of the dialog's `name: value` lines.
+pub fn check(fields: &[CustomField]) -> Result<(), String> {
+    for (i, field) in fields.iter().enumerate() {
+        if field.name.is_empty()
+            || !field
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        {
+            return Err(format!(
+                "Custom field names may only have lowercase letters, digits and underscores: {}",
+                field.name
+            ));
+        }
+        if RESERVED.contains(&field.name.as_str()) {
+            return Err(format!("Custom field name is reserved: {}", field.name));
+        }
+        if fields[..i].iter().any(|f| f.name == field.name) {
+            return Err(format!("Duplicate custom field: {}", field.name));
+        }
+        if field.field_type == FieldType::Select && field.options.is_empty() {
+            return Err(format!("Select custom field without options: {}", field.name));
+        }
+    }
+    Ok(())
+}
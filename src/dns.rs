@@ -0,0 +1,195 @@
+//! DNS names of an entry, written as `www.example.com A 300 primary`.
+
+extern crate serde;
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use self::serde::de::{Deserialize, Deserializer, Error};
+use self::serde::ser::{Serialize, Serializer};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum RecordType {
+    A,
+    Aaaa,
+    /// An alias pointing at one of the other names.
+    Cname,
+    /// The reverse record of the address.
+    Ptr,
+}
+
+impl RecordType {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            RecordType::A => "A",
+            RecordType::Aaaa => "AAAA",
+            RecordType::Cname => "CNAME",
+            RecordType::Ptr => "PTR",
+        }
+    }
+
+    /// The address record for the family of `ip`.
+    pub fn address(ip: &IpAddr) -> RecordType {
+        match *ip {
+            IpAddr::V4(_) => RecordType::A,
+            IpAddr::V6(_) => RecordType::Aaaa,
+        }
+    }
+
+    fn parse(s: &str) -> Option<RecordType> {
+        match s.to_uppercase().as_str() {
+            "A" => Some(RecordType::A),
+            "AAAA" => Some(RecordType::Aaaa),
+            "CNAME" => Some(RecordType::Cname),
+            "PTR" => Some(RecordType::Ptr),
+            _ => None,
+        }
+    }
+}
+
+/// A name pointing at the address of an entry.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DnsName {
+    pub name: String,
+    pub record: RecordType,
+    /// The name shown for the entry when only one fits.
+    pub primary: bool,
+    /// In seconds. The zone default applies when not given.
+    pub ttl: Option<u32>,
+}
+
+impl DnsName {
+    /// Parses a name whose record type may be left out, in which case it's
+    /// the address record for `ip`. An address record of the other family is
+    /// rejected.
+    pub fn parse(s: &str, ip: &IpAddr) -> Result<DnsName, String> {
+        let (mut name, explicit) = parse(s)?;
+        let address = RecordType::address(ip);
+        match explicit {
+            None => name.record = address,
+            Some(RecordType::A) | Some(RecordType::Aaaa) if name.record != address => {
+                return Err(format!(
+                    "{} record doesn't match the address: {}",
+                    name.record.as_str(),
+                    s
+                ))
+            }
+            _ => {}
+        }
+        Ok(name)
+    }
+}
+
+/// Splits a name into its tokens: the name first, then the record type, the
+/// TTL and `primary` in any order. Also returns the record type if one was
+/// given, since it defaults to `A`.
+fn parse(s: &str) -> Result<(DnsName, Option<RecordType>), String> {
+    let invalid = || format!("Invalid DNS name: {}", s);
+    let mut tokens = s.split_whitespace();
+    let name = tokens
+        .next()
+        .map(|n| n.trim_end_matches('.').to_lowercase())
+        .filter(|n| is_valid_name(n))
+        .ok_or_else(invalid)?;
+    let mut record = None;
+    let mut primary = false;
+    let mut ttl = None;
+    for token in tokens {
+        if let Some(r) = RecordType::parse(token) {
+            if record.is_some() {
+                return Err(invalid());
+            }
+            record = Some(r);
+        } else if token.eq_ignore_ascii_case("primary") {
+            primary = true;
+        } else if let Ok(t) = token.parse::<u32>() {
+            if ttl.is_some() {
+                return Err(invalid());
+            }
+            ttl = Some(t);
+        } else {
+            return Err(invalid());
+        }
+    }
+    Ok((
+        DnsName {
+            name,
+            record: record.unwrap_or(RecordType::A),
+            primary,
+            ttl,
+        },
+        record,
+    ))
+}
+
+/// Hostnames of letters, digits, hyphens and underscores, with labels of up
+/// to 63 characters.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty() && label.len() <= 63 && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+}
+
+impl FromStr for DnsName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DnsName, String> {
+        parse(s).map(|(name, _)| name)
+    }
+}
+
+impl fmt::Display for DnsName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.record.as_str())?;
+        if let Some(ttl) = self.ttl {
+            write!(f, " {}", ttl)?;
+        }
+        if self.primary {
+            write!(f, " primary")?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for DnsName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for DnsName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<DnsName, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Parses one name per line, returning the lines that couldn't be parsed as
+/// the error.
+pub fn parse_list(s: &str, ip: &IpAddr) -> Result<Vec<DnsName>, Vec<String>> {
+    let mut names: Vec<DnsName> = vec![];
+    let mut invalid = vec![];
+    for line in s.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+        match DnsName::parse(line, ip) {
+            Ok(name) => if !names
+                .iter()
+                .any(|n| n.name == name.name && n.record == name.record)
+            {
+                names.push(name)
+            },
+            Err(_) => invalid.push(line.to_owned()),
+        }
+    }
+    if invalid.is_empty() {
+        Ok(names)
+    } else {
+        Err(invalid)
+    }
+}
@@ -1,5 +1,7 @@
 extern crate config;
+extern crate notify;
 extern crate reqwest;
+extern crate rusqlite;
 extern crate serde_json;
 extern crate toml;
 
@@ -10,6 +12,9 @@ error_chain! {
         Json(serde_json::error::Error);
         Request(reqwest::Error);
         TomlSerialize(toml::ser::Error);
+        TomlDeserialize(toml::de::Error);
+        Sqlite(rusqlite::Error);
+        Notify(notify::Error);
     }
     errors {
         Poisoned(a: &'static str) {
@@ -32,5 +37,37 @@ error_chain! {
             description("submission not found")
             display("No such submission: {}", c)
         }
+        EntryNotFound(ip: String) {
+            description("entry not found")
+            display("No such IP: {}", ip)
+        }
+        StaleRevision(ip: String) {
+            description("entry changed since it was read")
+            display("IP {} has been changed by someone else", ip)
+        }
+        EntryExists(ip: String) {
+            description("entry already exists")
+            display("IP {} already exists", ip)
+        }
+        UnsupportedSchema(v: u32) {
+            description("unsupported schema version")
+            display("Schema version {} is newer than this server supports", v)
+        }
+        InvalidSchema(e: String) {
+            description("invalid record")
+            display("Invalid record: {}", e)
+        }
+        InvalidBackend(b: String) {
+            description("invalid storage backend")
+            display("No such storage backend: {}", b)
+        }
+        NotSupported(a: &'static str) {
+            description("not supported by the storage backend")
+            display("{} is not supported by the storage backend", a)
+        }
+        Git(e: String) {
+            description("git command failed")
+            display("Git command failed: {}", e)
+        }
     }
 }
@@ -0,0 +1,143 @@
+extern crate chrono;
+extern crate serde_derive;
+extern crate toml;
+
+use std::fmt;
+
+use self::chrono::{DateTime, Local, Utc};
+
+use super::error::Result;
+use super::ip::Entry;
+use super::slack::User;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Add,
+    Edit,
+    Delete,
+    Issue,
+    Revert,
+    Restore,
+}
+
+impl Action {
+    pub fn label(&self) -> &'static str {
+        match *self {
+            Action::Add => "추가",
+            Action::Edit => "수정",
+            Action::Delete => "삭제",
+            Action::Issue => "발급",
+            Action::Revert => "되돌림",
+            Action::Restore => "복구",
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Action::Add => "add",
+            Action::Edit => "edit",
+            Action::Delete => "delete",
+            Action::Issue => "issue",
+            Action::Revert => "revert",
+            Action::Restore => "restore",
+        })
+    }
+}
+
+/// A single field that differs between the entry before and after an event.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Change {
+    pub field: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} → {}",
+            self.field,
+            self.before.as_ref().map(|s| s.as_str()).unwrap_or("-"),
+            self.after.as_ref().map(|s| s.as_str()).unwrap_or("-")
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Event {
+    pub ip: String,
+    pub action: Action,
+    pub user: User,
+    pub timestamp: DateTime<Utc>,
+    pub changes: Vec<Change>,
+}
+
+impl Event {
+    pub fn new(
+        action: Action,
+        user: &User,
+        before: Option<&Entry>,
+        after: Option<&Entry>,
+    ) -> Result<Event> {
+        let ip = after
+            .or(before)
+            .map(|e| e.ip.to_string())
+            .unwrap_or_default();
+        Ok(Event {
+            ip,
+            action,
+            user: user.clone(),
+            timestamp: Utc::now(),
+            changes: diff(before, after)?,
+        })
+    }
+
+    pub fn local_time(&self) -> String {
+        self.timestamp
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    }
+}
+
+/// Compares two versions of an entry field by field. A missing entry counts
+/// as one with every field unset.
+pub fn diff(before: Option<&Entry>, after: Option<&Entry>) -> Result<Vec<Change>> {
+    use std::collections::BTreeMap;
+    use self::toml::Value;
+
+    fn fields(entry: Option<&Entry>) -> Result<BTreeMap<String, Value>> {
+        match entry.map(Value::try_from) {
+            Some(Ok(Value::Table(t))) => Ok(t.into_iter().collect()),
+            Some(Err(e)) => Err(e.into()),
+            _ => Ok(BTreeMap::new()),
+        }
+    }
+
+    fn render(value: &Value) -> String {
+        match *value {
+            Value::String(ref s) => s.clone(),
+            ref v => v.to_string(),
+        }
+    }
+
+    let before = fields(before)?;
+    let after = fields(after)?;
+    let mut keys: Vec<&String> = before.keys().chain(after.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    Ok(keys.into_iter()
+        .filter(|k| *k != "schema_version" && *k != "revision" && *k != "modified")
+        .filter(|k| before.get(*k) != after.get(*k))
+        .map(|k| Change {
+            field: k.clone(),
+            before: before.get(k).map(render),
+            after: after.get(k).map(render),
+        })
+        .collect())
+}
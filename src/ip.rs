@@ -1,36 +1,141 @@
+extern crate chrono;
 extern crate serde_derive;
 extern crate toml;
 
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv6Addr};
+
+use self::chrono::{DateTime, Local, NaiveDate, Utc};
+
+use super::custom::{CustomField, FieldType};
+use super::dns::{self, DnsName};
 use super::error::Result;
+use super::mac::MacAddr;
+use super::port::{self, PortSpec};
+use super::query::Expr;
+use super::schema;
+use super::slack::dialog::{SubmissionError, SubmissionErrors};
+use super::sort::Order;
+use super::status::Status;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Entry {
-    pub ip: String,
-    pub domain: Option<String>,
-    pub using: bool,
-    pub open_ports: Vec<u32>,
+    pub schema_version: u32,
+    /// Bumped on every edit, so a dialog opened on an older revision can be
+    /// told apart.
+    pub revision: u64,
+    /// When the entry was last saved. Unknown for entries saved before this
+    /// was recorded.
+    pub modified: Option<DateTime<Utc>>,
+    pub ip: IpAddr,
+    #[serde(default)] pub dns_names: Vec<DnsName>,
+    pub status: Status,
+    pub open_ports: Vec<PortSpec>,
     pub description: Option<String>,
+    /// Slack user ID of the person responsible for the address.
+    pub owner: Option<String>,
+    #[serde(default)] pub tags: Vec<String>,
+    /// The last day the address is lent for. Once it has passed, the address
+    /// may be issued to someone else.
+    pub lease_expires: Option<NaiveDate>,
+    #[serde(default)] pub macs: Vec<MacAddr>,
+    pub hostname: Option<String>,
+    pub model: Option<String>,
+    pub location: Option<String>,
+    /// Values of the custom fields of the settings, by field name. Kept in a
+    /// table of their own, so other keys added to a file by hand are ignored.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom: BTreeMap<String, String>,
 }
 
+/// Keys of the hardware details, as written in the dialog's textarea.
+const HARDWARE_KEYS: &[&str] = &["hostname", "model", "location"];
+
 impl Entry {
+    /// Parses an entry from its on-disk TOML form, upgrading records of an
+    /// older schema version.
+    pub fn from_toml(s: &str) -> Result<Entry> {
+        let mut value: toml::Value = s.parse()?;
+        schema::upgrade(&mut value)?;
+        Ok(value.try_into()?)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    /// Whether the entry is in use on a lease that ended before `today`.
+    pub fn is_lease_expired(&self, today: NaiveDate) -> bool {
+        self.status == Status::InUse && self.lease_expires.map_or(false, |d| d < today)
+    }
+
+    /// The name marked primary, or the first one if none is.
+    pub fn primary_name(&self) -> Option<&DnsName> {
+        self.dns_names
+            .iter()
+            .find(|n| n.primary)
+            .or_else(|| self.dns_names.first())
+    }
+
+    /// One name per line, in the format the dialog takes them back.
+    pub fn names_as_string(&self) -> String {
+        self.dns_names
+            .iter()
+            .map(|n| n.to_string())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub fn has_mac(&self, mac: &MacAddr) -> bool {
+        self.macs.contains(mac)
+    }
+
+    pub fn macs_as_string(&self) -> String {
+        self.macs
+            .iter()
+            .map(|m| m.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// The hardware details and the values of `fields` as `key: value`
+    /// lines, in the format the dialog takes them back. Further lines of a
+    /// multi-line value are indented.
+    pub fn details_as_string(&self, fields: &[CustomField]) -> String {
+        HARDWARE_KEYS
+            .iter()
+            .zip(&[&self.hostname, &self.model, &self.location])
+            .filter_map(|(k, v)| v.as_ref().map(|v| format!("{}: {}", k, v)))
+            .chain(fields.iter().filter_map(|f| {
+                self.custom
+                    .get(&f.name)
+                    .map(|v| format!("{}: {}", f.name, v.replace('\n', "\n  ")))
+            }))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
     pub fn ports_as_string(&self) -> String {
-        let mut s = String::new();
-        for p in &self.open_ports {
-            s.push_str(&format!("{}, ", p));
-        }
-        s.pop();
-        s.pop();
-        s
+        self.open_ports
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
     }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RawEntry {
     pub ip: String,
-    pub domain: Option<String>,
-    pub using: String,
+    pub dns_names: Option<String>,
+    pub status: String,
     pub open_ports: Option<String>,
     pub description: Option<String>,
+    pub owner: Option<String>,
+    pub tags: Option<String>,
+    pub lease_expires: Option<String>,
+    pub macs: Option<String>,
+    pub details: Option<String>,
 }
 
 pub struct Query {
@@ -38,203 +143,299 @@ pub struct Query {
     pub element: String,
 }
 
-impl Into<Entry> for RawEntry {
-    fn into(self) -> Entry {
-        let using = self.using == "true";
-        let open_ports = match self.open_ports {
-            None => vec![],
-            Some(s) => if !s.is_empty() {
-                s.split(',')
-                    .map(|s| s.trim())
-                    .map(|s| s.parse::<u32>())
-                    .filter(|s| s.is_ok())
-                    .map(|s| s.unwrap())
-                    .collect()
-            } else {
-                vec![]
-            },
-        };
-        Entry {
-            ip: self.ip,
-            domain: self.domain,
-            using,
-            open_ports,
-            description: self.description,
-        }
-    }
+/// Parses a user-typed address into the canonical form entries are stored
+/// under, e.g. `" 10.0.0.1"` to `"10.0.0.1"` and `"::FFFF:0:1"` to `"::ffff:0:1"`.
+pub fn normalize(ip: &str) -> Option<String> {
+    ip.trim().parse::<IpAddr>().ok().map(|ip| ip.to_string())
 }
 
-pub fn add(entry: &Entry, data_path: &str) -> Result<()> {
-    use std::fs::File;
-    use std::path::Path;
-    use std::io::Write;
-
-    let s = toml::to_string_pretty(entry)?;
-    let spath = format!("{}/{}.toml", data_path, entry.ip);
-    let p = Path::new(&spath);
-    let mut file: File = File::create(&p)?;
-    file.write_all(s.as_bytes())?;
-
-    Ok(())
+/// The fully expanded form of an IPv6 address, e.g.
+/// `2001:0db8:0000:0000:0000:0000:0000:0001` for `2001:db8::1`.
+pub fn expanded(ip: &Ipv6Addr) -> String {
+    ip.segments()
+        .iter()
+        .map(|s| format!("{:04x}", s))
+        .collect::<Vec<String>>()
+        .join(":")
 }
 
-pub fn get(ip: &str, data_path: &str) -> Option<Entry> {
-    use std::fs::File;
-    use std::path::Path;
-    use std::io::Read;
-
-    let spath = format!("{}/{}.toml", data_path, ip);
-    let p = Path::new(&spath);
-    let mut file: File = match File::open(&p) {
-        Ok(f) => f,
-        Err(_) => return None,
-    };
-    let mut content = String::new();
-    if file.read_to_string(&mut content).is_err() {
+/// Parses a search term ending with a colon, like `2001:db8:` or `fe80::`,
+/// into the leading IPv6 segments it fixes.
+pub fn ipv6_prefix(q: &str) -> Option<Vec<u16>> {
+    if !q.ends_with(':') {
+        return None;
+    }
+    let body = q.trim_end_matches(':');
+    if body.is_empty() || body.contains("::") {
         return None;
     }
-    toml::from_str(&content).ok()
+    let segments = body.split(':')
+        .map(|s| if s.is_empty() || s.len() > 4 {
+            None
+        } else {
+            u16::from_str_radix(s, 16).ok()
+        })
+        .collect::<Option<Vec<u16>>>()?;
+    if segments.len() > 8 {
+        return None;
+    }
+    Some(segments)
 }
 
-pub fn list(query: &str, data_path: &str) -> Vec<Query> {
-    use std::fs::{read_dir, DirEntry, File, ReadDir};
-    use std::io::Read;
-
-    let dir_entries: ReadDir = match read_dir(data_path) {
-        Ok(d) => d,
-        Err(_) => return vec![],
-    };
-    let files: Vec<DirEntry> = dir_entries
-        .filter(|e| e.is_ok())
-        .map(|e| e.unwrap())
-        .collect();
-    let entries = files.into_iter().map(|f| {
-        let mut file = File::open(f.path()).unwrap();
-        let mut content: String = String::new();
-        file.read_to_string(&mut content).unwrap();
-        toml::from_str::<Entry>(&content).unwrap()
-    });
-    let entries: Vec<Query> = if !query.is_empty() {
-        entries
-            .filter_map(|e| {
-                query
-                    .split(' ')
-                    .filter(|q| !q.is_empty())
-                    .filter_map(|q| generate_query(&e, q))
-                    .next()
-            })
-            .take(8)
-            .collect()
-    } else {
-        entries
-            .map(|e| {
-                Query {
-                    ip: e.ip.clone(),
-                    element: {
-                        let mut s = String::new();
-                        if let Some(ref domain) = e.domain {
-                            s.push_str(domain);
-                            s.push_str("\n");
-                        }
-                        s.push_str(if e.using { "사용중" } else { "미사용" });
-                        s
-                    },
-                }
-            })
-            .take(8)
-            .collect()
-    };
-    entries
+pub fn has_prefix(ip: &IpAddr, prefix: &[u16]) -> bool {
+    match *ip {
+        IpAddr::V6(ref ip) => ip.segments().starts_with(prefix),
+        IpAddr::V4(_) => false,
+    }
 }
 
-fn generate_query(entry: &Entry, q: &str) -> Option<Query> {
-    if entry.ip.contains(q) {
-        return Some(Query {
-            ip: entry.ip.clone(),
-            element: {
-                let mut s = String::new();
-                if let Some(ref domain) = entry.domain {
-                    s.push_str(domain);
-                    s.push_str("\n");
-                }
-                s.push_str(if entry.using {
-                    "사용중"
-                } else {
-                    "미사용"
+impl RawEntry {
+    /// Validates a dialog submission, returning the errors to show next to the
+    /// offending elements when it's rejected.
+    pub fn into_entry(
+        self,
+        fields: &[CustomField],
+    ) -> ::std::result::Result<Entry, SubmissionErrors> {
+        let mut errors = vec![];
+        let ip = match self.ip.trim().parse::<IpAddr>() {
+            Ok(ip) => Some(ip),
+            Err(_) => {
+                errors.push(SubmissionError {
+                    name: "ip".to_owned(),
+                    error: "올바른 IP 주소가 아닙니다.".to_owned(),
+                });
+                None
+            }
+        };
+        let mut dns_names = vec![];
+        if let Some(ip) = ip {
+            match dns::parse_list(self.dns_names.as_ref().map_or("", |s| s.as_str()), &ip) {
+                Ok(names) => dns_names = names,
+                Err(invalid) => errors.push(SubmissionError {
+                    name: "dns_names".to_owned(),
+                    error: format!("올바른 DNS 이름이 아닙니다: {}", invalid.join(", ")),
+                }),
+            }
+        }
+        match dns_names.iter().filter(|n| n.primary).count() {
+            0 => if let Some(first) = dns_names.first_mut() {
+                first.primary = true;
+            },
+            1 => {}
+            _ => errors.push(SubmissionError {
+                name: "dns_names".to_owned(),
+                error: "대표 이름은 하나만 지정할 수 있습니다.".to_owned(),
+            }),
+        }
+        let status = match self.status.parse::<Status>() {
+            Ok(status) => status,
+            Err(_) => {
+                errors.push(SubmissionError {
+                    name: "status".to_owned(),
+                    error: "올바른 상태가 아닙니다.".to_owned(),
+                });
+                Status::Available
+            }
+        };
+        let open_ports = match port::parse_list(self.open_ports.as_ref().map_or("", |s| s.as_str()), ',') {
+            Ok(ports) => ports,
+            Err(invalid) => {
+                errors.push(SubmissionError {
+                    name: "open_ports".to_owned(),
+                    error: format!("올바른 포트가 아닙니다: {}", invalid.join(", ")),
                 });
-                s
+                vec![]
+            }
+        };
+        let mut tags: Vec<String> = vec![];
+        for tag in self.tags.as_ref().map_or("", |s| s.as_str()).split(',') {
+            let tag = tag.trim();
+            if tag.contains(char::is_whitespace) {
+                errors.push(SubmissionError {
+                    name: "tags".to_owned(),
+                    error: format!("태그에는 공백을 쓸 수 없습니다: {}", tag),
+                });
+                break;
+            }
+            if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+                tags.push(tag.to_owned());
+            }
+        }
+        let lease_expires = match self.lease_expires.as_ref().map(|d| d.trim()) {
+            None | Some("") => None,
+            Some(d) => match NaiveDate::parse_from_str(d, "%Y-%m-%d") {
+                Ok(d) => Some(d),
+                Err(_) => {
+                    errors.push(SubmissionError {
+                        name: "lease_expires".to_owned(),
+                        error: "날짜는 YYYY-MM-DD 형식이어야 합니다.".to_owned(),
+                    });
+                    None
+                }
             },
-        });
-    }
-    if let Some(ref domain) = entry.domain {
-        if domain.contains(q) {
-            return Some(Query {
-                ip: entry.ip.clone(),
-                element: entry.domain.as_ref().unwrap().clone(),
-            });
+        };
+        let mut macs: Vec<MacAddr> = vec![];
+        let mut invalid_macs = vec![];
+        for m in self.macs.as_ref().map_or("", |s| s.as_str()).split(',') {
+            match m.trim() {
+                "" => {}
+                m => match m.parse() {
+                    Ok(mac) => if !macs.contains(&mac) {
+                        macs.push(mac)
+                    },
+                    Err(_) => invalid_macs.push(m.to_owned()),
+                },
+            }
         }
-    }
-    if entry.using && q == "사용중" {
-        return Some(Query {
-            ip: entry.ip.clone(),
-            element: "사용중".to_owned(),
-        });
-    }
-    if !entry.using && q == "미사용" {
-        return Some(Query {
-            ip: entry.ip.clone(),
-            element: "미사용".to_owned(),
-        });
-    }
-    if let Ok(i) = q.parse::<u32>() {
-        if entry.open_ports.contains(&i) {
-            return Some(Query {
-                ip: entry.ip.clone(),
-                element: entry.ports_as_string(),
+        if !invalid_macs.is_empty() {
+            errors.push(SubmissionError {
+                name: "macs".to_owned(),
+                error: format!("올바른 MAC 주소가 아닙니다: {}", invalid_macs.join(", ")),
             });
         }
+        let (mut hostname, mut model, mut location) = (None, None, None);
+        let mut custom: BTreeMap<String, String> = BTreeMap::new();
+        // The textarea custom field the previous line started, which indented
+        // lines continue.
+        let mut continued: Option<&CustomField> = None;
+        for line in self.details.as_ref().map_or("", |s| s.as_str()).lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let (true, Some(field)) = (line.starts_with(char::is_whitespace), continued) {
+                let value = custom.entry(field.name.clone()).or_insert_with(String::new);
+                if !value.is_empty() {
+                    value.push('\n');
+                }
+                value.push_str(line.trim());
+                continue;
+            }
+            let mut kv = line.splitn(2, ':');
+            let key = kv.next().unwrap_or("").trim().to_lowercase();
+            let value = kv.next()
+                .map(|v| v.trim().to_owned())
+                .map(|v| if v.is_empty() { None } else { Some(v) });
+            continued = None;
+            match (key.as_str(), value) {
+                ("hostname", Some(value)) => hostname = value,
+                ("model", Some(value)) => model = value,
+                ("location", Some(value)) => location = value,
+                (key, Some(value)) if fields.iter().any(|f| f.name == key) => {
+                    let field = fields.iter().find(|f| f.name == key).unwrap();
+                    if field.field_type == FieldType::Textarea {
+                        continued = Some(field);
+                    }
+                    if let Some(value) = value {
+                        custom.insert(field.name.clone(), value);
+                    }
+                }
+                _ => {
+                    let keys: Vec<&str> = HARDWARE_KEYS
+                        .iter()
+                        .cloned()
+                        .chain(fields.iter().map(|f| f.name.as_str()))
+                        .collect();
+                    errors.push(SubmissionError {
+                        name: "details".to_owned(),
+                        error: format!(
+                            "한 줄에 하나씩 '{}: 값' 형식으로 적어 주세요: {}",
+                            keys.join(" | "),
+                            line.trim()
+                        ),
+                    });
+                    break;
+                }
+            }
+        }
+        let ip = match ip {
+            Some(ip) if errors.is_empty() => ip,
+            _ => return Err(SubmissionErrors { errors }),
+        };
+        Ok(Entry {
+            schema_version: schema::CURRENT_VERSION,
+            revision: 0,
+            modified: None,
+            ip,
+            dns_names,
+            status,
+            open_ports,
+            description: self.description,
+            owner: self.owner.filter(|o| !o.is_empty()),
+            tags,
+            lease_expires,
+            macs,
+            hostname,
+            model,
+            location,
+            custom,
+        })
     }
-    if entry.description.is_some() && entry.description.as_ref().unwrap().contains(q) {
-        return Some(Query {
-            ip: entry.ip.clone(),
-            element: entry.description.as_ref().unwrap().clone(),
-        });
-    }
-    None
 }
 
-pub fn issue(required_ports: &[u32], data_path: &str) -> Option<Entry> {
-    use std::fs::{read_dir, DirEntry, File, ReadDir};
-    use std::io::Read;
-
-    let dir_entries: ReadDir = match read_dir(data_path) {
-        Ok(r) => r,
-        Err(_) => return None,
-    };
-    let files: Vec<DirEntry> = dir_entries
-        .filter(|e| e.is_ok())
-        .map(|e| e.unwrap())
+/// The entries matching `query` in `order`, each with the value that matched
+/// it or a summary of it.
+pub fn list<'a, I>(query: &Expr, order: &Order, entries: I) -> Vec<Query>
+where
+    I: IntoIterator<Item = &'a Entry>,
+{
+    let mut matches: Vec<(&Entry, Option<String>)> = entries
+        .into_iter()
+        .filter_map(|e| query.eval(e).map(|element| (e, element)))
         .collect();
-    files
+    matches.sort_by(|a, b| order.compare(a.0, b.0));
+    matches
         .into_iter()
-        .map(|f| {
-            let mut file = File::open(f.path()).unwrap();
-            let mut content: String = String::new();
-            file.read_to_string(&mut content).unwrap();
-            toml::from_str::<Entry>(&content).unwrap()
-        })
-        .find(|e| {
-            !e.using
-                && (required_ports.is_empty()
-                    || required_ports.into_iter().all(|p| e.open_ports.contains(p)))
+        .map(|(e, element)| Query {
+            ip: e.ip.to_string(),
+            element: element.unwrap_or_else(|| summary(e)),
         })
+        .collect()
+}
+
+fn summary(entry: &Entry) -> String {
+    let mut s = String::new();
+    if let Some(name) = entry.primary_name() {
+        s.push_str(&name.name);
+        s.push_str("\n");
+    }
+    s.push_str(entry.status.label());
+    s
+}
+
+/// Finds an entry to issue, preferring available entries to expired leases.
+pub fn issue<'a, I>(required_ports: &[PortSpec], entries: I) -> Option<Entry>
+where
+    I: IntoIterator<Item = &'a Entry>,
+{
+    let (unused, leased): (Vec<&Entry>, Vec<&Entry>) = entries
+        .into_iter()
+        .filter(|e| is_issuable(e, required_ports))
+        .partition(|e| e.status == Status::Available);
+    unused.into_iter().chain(leased).next().cloned()
+}
+
+/// The local date leases are compared against.
+pub fn today() -> NaiveDate {
+    Local::now().naive_local().date()
+}
+
+/// Available entries and the ones on an expired lease can be issued.
+pub fn is_issuable(entry: &Entry, required_ports: &[PortSpec]) -> bool {
+    (entry.status == Status::Available || entry.is_lease_expired(today()))
+        && required_ports
+            .iter()
+            .all(|r| entry.open_ports.iter().any(|p| p.covers(r)))
 }
 
-pub fn delete(ip: &str, data_path: &str) -> Result<()> {
-    use std::fs::remove_file;
-    use std::path::Path;
-    let spath = format!("{}/{}.toml", data_path, ip);
-    let p = Path::new(&spath);
-    remove_file(p)?;
-    Ok(())
+/// Entries in use on a lease ending on or before `until`, soonest first.
+pub fn leases<'a, I>(until: NaiveDate, entries: I) -> Vec<&'a Entry>
+where
+    I: IntoIterator<Item = &'a Entry>,
+{
+    let mut leases: Vec<&Entry> = entries
+        .into_iter()
+        .filter(|e| e.status == Status::InUse && e.lease_expires.map_or(false, |d| d <= until))
+        .collect();
+    leases.sort_by_key(|e| e.lease_expires);
+    leases
 }
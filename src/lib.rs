@@ -1,5 +1,6 @@
 #![feature(plugin, custom_derive, decl_macro)]
 #![plugin(rocket_codegen)]
+extern crate chrono;
 #[macro_use]
 extern crate error_chain;
 #[macro_use]
@@ -14,8 +15,19 @@ extern crate serde_json;
 pub mod error;
 pub use error::{ErrorKind, Result};
 
+pub mod custom;
+pub mod dns;
+pub mod history;
 pub mod ip;
+pub mod mac;
+pub mod port;
+pub mod query;
+pub mod schema;
 pub mod slack;
+pub mod sort;
+pub mod status;
+pub mod store;
+pub mod subnet;
 pub mod settings {
     extern crate config;
 
@@ -24,6 +36,13 @@ pub mod settings {
         pub verification_token: String,
         pub api_token: String,
         pub data_path: String,
+        pub backend: Option<String>,
+        pub sqlite_path: Option<String>,
+        pub git: Option<bool>,
+        pub index: Option<bool>,
+        pub trash_purge_days: Option<i64>,
+        pub subnets: Option<Vec<::subnet::Subnet>>,
+        pub custom_fields: Option<Vec<::custom::CustomField>>,
     }
 
     impl Settings {
@@ -40,14 +59,37 @@ pub mod settings {
             settings.try_into::<Settings>()
         }
 
+        pub fn trash_purge_days(&self) -> i64 {
+            self.trash_purge_days.unwrap_or(30)
+        }
+
+        pub fn subnets(&self) -> Vec<::subnet::Subnet> {
+            self.subnets.clone().unwrap_or_default()
+        }
+
+        pub fn custom_fields(&self) -> Vec<::custom::CustomField> {
+            self.custom_fields.clone().unwrap_or_default()
+        }
+
+        pub fn sqlite_path(&self) -> String {
+            self.sqlite_path
+                .clone()
+                .unwrap_or_else(|| "./ip_manager.sqlite".to_owned())
+        }
+
         pub fn assure_new() -> Settings {
             use std::fs::read_dir;
             let s = match Settings::try_new() {
                 Ok(s) => s,
                 Err(e) => panic!("Settings file parse error!, {}", e),
             };
-            if let Err(e) = read_dir(&s.data_path) {
-                panic!("Invalid data folder. Check settings file!, {}", e);
+            if let Err(e) = ::custom::check(&s.custom_fields()) {
+                panic!("Invalid custom field. Check settings file!, {}", e);
+            }
+            if s.backend.is_none() || s.backend == Some("file".to_owned()) {
+                if let Err(e) = read_dir(&s.data_path) {
+                    panic!("Invalid data folder. Check settings file!, {}", e);
+                }
             }
             s
         }
@@ -56,6 +98,13 @@ pub mod settings {
 
 lazy_static! {
     static ref SETTINGS: std::sync::RwLock<settings::Settings> = std::sync::RwLock::new(settings::Settings::assure_new());
+    static ref STORE: Box<store::Store> = {
+        let settings = SETTINGS.read().expect("Lock poisoned at settings");
+        match store::open(&settings) {
+            Ok(s) => s,
+            Err(e) => panic!("Storage open error! Check settings file!, {}", e),
+        }
+    };
 }
 
 fn verification_token() -> Result<String> {
@@ -65,6 +114,13 @@ fn verification_token() -> Result<String> {
         .map_err(|_| ErrorKind::Poisoned("verification_token").into())
 }
 
+fn context() -> Result<Context> {
+    SETTINGS
+        .read()
+        .map(|settings| Context::new(&settings))
+        .map_err(|_| ErrorKind::Poisoned("context").into())
+}
+
 fn api_token() -> Result<String> {
     SETTINGS
         .read()
@@ -72,11 +128,44 @@ fn api_token() -> Result<String> {
         .map_err(|_| ErrorKind::Poisoned("api_token").into())
 }
 
-fn data_path() -> Result<String> {
-    SETTINGS
+/// Imports every entry of the `data_path` folder into the SQLite database at
+/// `sqlite_path`. Returns the number of imported entries.
+pub fn import_data_path() -> Result<usize> {
+    let settings = SETTINGS
         .read()
-        .map(|settings| settings.data_path.clone())
-        .map_err(|_| ErrorKind::Poisoned("data_path").into())
+        .map_err(|_| ErrorKind::Poisoned("settings"))?;
+    let sqlite = store::SqliteStore::open(&settings.sqlite_path())?;
+    sqlite.import(&store::FileStore::new(&settings.data_path))
+}
+
+/// Checks the configured store for damaged entries, moving the unusable
+/// ones to the quarantine when `quarantine` is set.
+pub fn fsck(quarantine: bool) -> Result<store::FsckReport> {
+    STORE.fsck(quarantine)
+}
+
+/// Rewrites every stored entry in the current schema version. Returns the
+/// number of rewritten entries.
+pub fn migrate() -> Result<usize> {
+    STORE.migrate()
+}
+
+/// The part of the settings the handlers need, passed along with the store so
+/// they don't read the settings file themselves.
+pub struct Context {
+    pub custom_fields: Vec<custom::CustomField>,
+    pub subnets: Vec<subnet::Subnet>,
+    pub trash_purge_days: i64,
+}
+
+impl Context {
+    pub fn new(settings: &settings::Settings) -> Context {
+        Context {
+            custom_fields: settings.custom_fields(),
+            subnets: settings.subnets(),
+            trash_purge_days: settings.trash_purge_days(),
+        }
+    }
 }
 
 pub enum Response {
@@ -94,13 +183,26 @@ pub fn handle_command(
         bail!(ErrorKind::InvalidToken);
     }
 
+    let store: &store::Store = &**STORE;
+    let context = &context()?;
+    let user = slack::User {
+        id: data.user_id.clone(),
+        name: data.user_name.clone(),
+    };
     let result = match command {
-        "add" => add_command(),
-        "get" => get_command(&data.text),
-        "edit" => edit_command(&data.text),
-        "list" => list_command(&data.text),
-        "issue" => issue_command(&data.text),
-        "del" => del_command(&data.text),
+        "add" => add_command(context),
+        "get" => get_command(store, context, &data.text),
+        "edit" => edit_command(store, context, &data.text),
+        "list" => list_command(store, context, &data.text),
+        "issue" => issue_command(store, context, &data.text),
+        "del" => del_command(store, context, &user, &data.text),
+        "history" => history_command(store, &data.text),
+        "revert" => revert_command(store, &user, &data.text),
+        "trash" => trash_command(store, context),
+        "restore" => restore_command(store, &user, &data.text),
+        "fsck" => fsck_command(store),
+        "subnet" => subnet_command(store, context, &data.text),
+        "leases" => leases_command(store, &data.text),
         _ => bail!(ErrorKind::CommandNotFound(command.to_string())),
     }?;
 
@@ -115,7 +217,11 @@ pub fn handle_command(
     }
 }
 
-pub fn handle_submission(submission: slack::dialog::Submission) -> Result<()> {
+/// Handles a dialog submission. Returns the errors to show in the dialog when
+/// the submission is rejected.
+pub fn handle_submission(
+    submission: slack::dialog::Submission,
+) -> Result<Option<slack::dialog::SubmissionErrors>> {
     if verification_token()? != submission.token {
         bail!(ErrorKind::InvalidToken);
     }
@@ -123,91 +229,525 @@ pub fn handle_submission(submission: slack::dialog::Submission) -> Result<()> {
         bail!(ErrorKind::InvalidSubmission);
     }
 
+    let store: &store::Store = &**STORE;
+    let context = &context()?;
     match submission.callback_id.as_ref() {
-        "add" => add_submission(submission),
-        "edit" => edit_submission(submission),
+        "add" => add_submission(store, context, submission),
+        "edit" => edit_submission(store, context, submission),
+        "issue" => issue_submission(store, context, submission),
         _ => bail!(ErrorKind::SubmissionNotFound(submission.callback_id)),
     }
 }
 
-fn add_command() -> Result<Response> {
-    Ok(Response::Dialog(generate_add_dialog()))
+/// Handles a click on a button of a message. Returns the message to replace
+/// the clicked one with.
+pub fn handle_action(payload: slack::interactive::Payload) -> Result<serde_json::Value> {
+    if verification_token()? != payload.token {
+        bail!(ErrorKind::InvalidToken);
+    }
+    if payload.payload_type != "interactive_message" {
+        bail!(ErrorKind::InvalidSubmission);
+    }
+
+    let store: &store::Store = &**STORE;
+    let context = &context()?;
+    let value = match payload.actions.first() {
+        Some(action) => &action.value,
+        None => bail!(ErrorKind::InvalidSubmission),
+    };
+    let result = match payload.callback_id.as_ref() {
+        "list" => list_action(store, context, value),
+        _ => bail!(ErrorKind::SubmissionNotFound(payload.callback_id.clone())),
+    }?;
+
+    match result {
+        Response::PlainText(t) => Ok(json!({ "text": t })),
+        Response::AttachedMessage(m) => Ok(serde_json::to_value(m)?),
+        Response::Json(j) => Ok(j),
+        Response::Dialog(_) => bail!(ErrorKind::InvalidSubmission),
+    }
+}
+
+fn add_command(context: &Context) -> Result<Response> {
+    Ok(Response::Dialog(generate_add_dialog(&context.custom_fields)))
 }
 
-fn get_command(query: &str) -> Result<Response> {
-    use ip::get;
+fn get_command(store: &store::Store, context: &Context, query: &str) -> Result<Response> {
     if query.is_empty() {
         return Ok(Response::PlainText("Invalid argument".to_owned()));
     }
-    let entry = get(query, &data_path()?);
+    let entry = if let Some(ip) = ip::normalize(query) {
+        store.get(&ip)
+    } else if let Ok(mac) = query.parse::<mac::MacAddr>() {
+        store.with_mac(&mac)
+    } else {
+        return Ok(Response::PlainText("Invalid IP address".to_owned()));
+    };
     match entry {
-        Some(e) => Ok(Response::AttachedMessage(generate_get_message(e))),
+        Some(e) => {
+            let subnet = subnet::containing(&context.subnets, &e.ip);
+            Ok(Response::AttachedMessage(generate_get_message(
+                e,
+                subnet,
+                &context.custom_fields,
+            )))
+        }
         None => Ok(Response::PlainText("IP not found".to_owned())),
     }
 }
 
-fn edit_command(query: &str) -> Result<Response> {
-    use ip::get;
+fn edit_command(store: &store::Store, context: &Context, query: &str) -> Result<Response> {
     if query.is_empty() {
         return Ok(Response::PlainText("Invalid argument".to_owned()));
     }
-    let entry = match get(query, &data_path()?) {
+    let query = match ip::normalize(query) {
+        Some(ip) => ip,
+        None => return Ok(Response::PlainText("Invalid IP address".to_owned())),
+    };
+    let entry = match store.get(&query) {
         None => return Ok(Response::PlainText("IP not found".to_owned())),
         Some(e) => e,
     };
 
-    Ok(Response::Dialog(generate_edit_dialog(entry)))
+    Ok(Response::Dialog(generate_edit_dialog(entry, &context.custom_fields)))
+}
+
+/// The number of results on a page of `/ip-list`.
+const PAGE_SIZE: usize = 8;
+
+fn list_command(store: &store::Store, context: &Context, query: &str) -> Result<Response> {
+    list_page(store, context, query, 0)
+}
+
+/// The value of the `/ip-list` page buttons is the offset of the page
+/// followed by the query.
+fn list_action(store: &store::Store, context: &Context, value: &str) -> Result<Response> {
+    let mut split = value.splitn(2, ' ');
+    match split.next().and_then(|o| o.parse::<usize>().ok()) {
+        Some(offset) => list_page(store, context, split.next().unwrap_or(""), offset),
+        None => bail!(ErrorKind::InvalidSubmission),
+    }
 }
 
-fn list_command(query: &str) -> Result<Response> {
-    use ip::list;
-    let entries = list(query, &data_path()?);
+/// Shows the results of `query` from the `offset`th one on.
+fn list_page(
+    store: &store::Store,
+    context: &Context,
+    query: &str,
+    offset: usize,
+) -> Result<Response> {
+    let custom: Vec<String> = context
+        .custom_fields
+        .iter()
+        .map(|f| f.name.clone())
+        .collect();
+    let (expr, order) = match query::parse_sorted(query, &custom) {
+        Ok(parsed) => parsed,
+        Err(e) => return Ok(Response::PlainText(format!("Invalid query: {}", e))),
+    };
+    let entries = store.list(&expr, &order);
     if entries.is_empty() {
         return Ok(Response::PlainText("IP not found".to_owned()));
     }
+    // Entries may have been deleted since the buttons were made.
+    let offset = offset.min((entries.len() - 1) / PAGE_SIZE * PAGE_SIZE);
     Ok(Response::AttachedMessage(
-        generate_list_message(query, entries),
+        generate_list_message(query, entries, offset),
     ))
 }
 
-fn issue_command(ports: &str) -> Result<Response> {
-    use ip::issue;
-    match issue(
-        &ports
-            .split(' ')
-            .filter_map(|p| p.parse::<u32>().ok())
-            .collect::<Vec<u32>>(),
-        &data_path()?,
-    ) {
-        Some(e) => Ok(Response::Dialog(generate_edit_dialog(e))),
+fn issue_command(store: &store::Store, context: &Context, ports: &str) -> Result<Response> {
+    let ports = match port::parse_list(ports, ' ') {
+        Ok(ports) => ports,
+        Err(invalid) => {
+            return Ok(Response::PlainText(format!(
+                "Invalid port: {}",
+                invalid.join(", ")
+            )))
+        }
+    };
+    match store.issue(&ports) {
+        Some(mut e) => {
+            if e.is_lease_expired(ip::today()) {
+                // Reclaimed from an expired lease; issued like an unused one.
+                e.status = status::Status::Available;
+                e.owner = None;
+                e.lease_expires = None;
+            }
+            Ok(Response::Dialog(generate_issue_dialog(e, &context.custom_fields)))
+        }
         None => Ok(Response::PlainText("No available IP".to_owned())),
     }
 }
 
-fn del_command(ip: &str) -> Result<Response> {
-    use ip::delete;
+fn leases_command(store: &store::Store, days: &str) -> Result<Response> {
+    use chrono::Duration;
+    let days = match days.trim() {
+        "" => 14,
+        d => match d.parse::<i64>() {
+            Ok(d) if d >= 0 => d,
+            _ => return Ok(Response::PlainText("Invalid argument".to_owned())),
+        },
+    };
+    let today = ip::today();
+    let entries = store.entries();
+    let leases = ip::leases(today + Duration::days(days), &entries);
+    if leases.is_empty() {
+        return Ok(Response::PlainText("No lease expiring".to_owned()));
+    }
+    Ok(Response::AttachedMessage(
+        generate_leases_message(days, today, &leases),
+    ))
+}
+
+fn del_command(
+    store: &store::Store,
+    context: &Context,
+    user: &slack::User,
+    ip: &str,
+) -> Result<Response> {
+    use history::{Action, Event};
     if ip.is_empty() {
         return Ok(Response::PlainText("Invalid argument".to_owned()));
     }
-    delete(ip, &data_path()?)?;
-    Ok(Response::PlainText(format!("IP {} deleted", ip)))
+    let ip = match ip::normalize(ip) {
+        Some(ip) => ip,
+        None => return Ok(Response::PlainText("Invalid IP address".to_owned())),
+    };
+    let before = match store.delete(&ip, user) {
+        Ok(entry) => entry,
+        Err(ref e) if is_not_found(e) => return Ok(Response::PlainText("IP not found".to_owned())),
+        Err(e) => return Err(e),
+    };
+    store.record(&Event::new(Action::Delete, user, Some(&before), None)?)?;
+    purge_trash(store, context)?;
+    Ok(Response::PlainText(format!("IP {} moved to trash", ip)))
 }
 
-fn add_submission(submission: slack::dialog::Submission) -> Result<()> {
-    use ip::{add, Entry};
-    let entry: Entry = submission.submission.into();
-    add(&entry, &data_path()?)?;
-    Ok(())
+fn trash_command(store: &store::Store, context: &Context) -> Result<Response> {
+    purge_trash(store, context)?;
+    let trashed = store.trash();
+    if trashed.is_empty() {
+        return Ok(Response::PlainText("Trash is empty".to_owned()));
+    }
+    Ok(Response::AttachedMessage(generate_trash_message(trashed)))
 }
 
-fn edit_submission(submission: slack::dialog::Submission) -> Result<()> {
-    use ip::{add, Entry};
-    let entry: Entry = submission.submission.into();
-    add(&entry, &data_path()?)?;
-    Ok(())
+fn restore_command(store: &store::Store, user: &slack::User, ip: &str) -> Result<Response> {
+    use history::{Action, Event};
+    if ip.is_empty() {
+        return Ok(Response::PlainText("Invalid argument".to_owned()));
+    }
+    let ip = match ip::normalize(ip) {
+        Some(ip) => ip,
+        None => return Ok(Response::PlainText("Invalid IP address".to_owned())),
+    };
+    let entry = match store.restore(&ip) {
+        Ok(Some(e)) => e,
+        Ok(None) => return Ok(Response::PlainText("IP not found in trash".to_owned())),
+        Err(ref e) if is_exists(e) => {
+            return Ok(Response::PlainText(format!(
+                "IP {} already exists, delete it first",
+                ip
+            )))
+        }
+        Err(e) => return Err(e),
+    };
+    store.record(&Event::new(Action::Restore, user, None, Some(&entry))?)?;
+    Ok(Response::PlainText(format!("IP {} restored", ip)))
+}
+
+fn subnet_command(store: &store::Store, context: &Context, name: &str) -> Result<Response> {
+    let subnets = &context.subnets;
+    if subnets.is_empty() {
+        return Ok(Response::PlainText("No subnet defined".to_owned()));
+    }
+    let entries = store.entries();
+    let name = name.trim();
+    if name.is_empty() {
+        return Ok(Response::AttachedMessage(
+            generate_subnet_list_message(&subnets, &entries),
+        ));
+    }
+    match subnets.iter().find(|s| s.name == name) {
+        Some(s) => Ok(Response::AttachedMessage(generate_subnet_message(s, &entries))),
+        None => Ok(Response::PlainText("Subnet not found".to_owned())),
+    }
+}
+
+fn fsck_command(store: &store::Store) -> Result<Response> {
+    let report = store.fsck(false)?;
+    Ok(Response::AttachedMessage(generate_fsck_message(report)))
+}
+
+fn purge_trash(store: &store::Store, context: &Context) -> Result<usize> {
+    use chrono::{Duration, Utc};
+    store.purge(Utc::now() - Duration::days(context.trash_purge_days))
+}
+
+fn history_command(store: &store::Store, ip: &str) -> Result<Response> {
+    if ip.is_empty() {
+        return Ok(Response::PlainText("Invalid argument".to_owned()));
+    }
+    let ip = match ip::normalize(ip) {
+        Some(ip) => ip,
+        None => return Ok(Response::PlainText("Invalid IP address".to_owned())),
+    };
+    let events = store.history(&ip);
+    if events.is_empty() {
+        return Ok(Response::PlainText("No history".to_owned()));
+    }
+    Ok(Response::AttachedMessage(generate_history_message(&ip, events)))
+}
+
+fn revert_command(store: &store::Store, user: &slack::User, args: &str) -> Result<Response> {
+    use history::{Action, Event};
+    let mut args = args.split(' ').filter(|a| !a.is_empty());
+    let ip = match args.next() {
+        Some(ip) => ip,
+        None => return Ok(Response::PlainText("Invalid argument".to_owned())),
+    };
+    let ip = match ip::normalize(ip) {
+        Some(ip) => ip,
+        None => return Ok(Response::PlainText("Invalid IP address".to_owned())),
+    };
+    let before = store.get(&ip);
+    let entry = match store.revision(&ip, args.next()) {
+        Ok(Some(e)) => e,
+        Ok(None) => return Ok(Response::PlainText("Revision not found".to_owned())),
+        Err(ref e) if is_not_supported(e) => {
+            return Ok(Response::PlainText(
+                "Revert is not supported by the storage backend".to_owned(),
+            ))
+        }
+        Err(e) => return Err(e),
+    };
+    // Checked against the entry read before, so a concurrent edit isn't
+    // silently overwritten. A deleted entry starts over from the revision.
+    let saved = match before {
+        Some(ref before) => store.update(&entry, before.revision),
+        None => store.create(&entry),
+    };
+    let entry = match saved {
+        Ok(saved) => saved,
+        Err(ref e) if is_stale(e) || is_not_found(e) || is_exists(e) => {
+            return Ok(Response::PlainText(format!(
+                "IP {} has been changed by someone else. Try again",
+                ip
+            )))
+        }
+        Err(e) => return Err(e),
+    };
+    store.record(&Event::new(
+        Action::Revert,
+        user,
+        before.as_ref(),
+        Some(&entry),
+    )?)?;
+    Ok(Response::PlainText(format!("IP {} reverted", ip)))
+}
+
+type SubmissionResult = Result<Option<slack::dialog::SubmissionErrors>>;
+
+fn add_submission(
+    store: &store::Store,
+    context: &Context,
+    submission: slack::dialog::Submission,
+) -> SubmissionResult {
+    save_submission(store, context, history::Action::Add, submission)
 }
 
-fn generate_get_message(entry: ip::Entry) -> slack::AttachedMessage {
+fn edit_submission(
+    store: &store::Store,
+    context: &Context,
+    submission: slack::dialog::Submission,
+) -> SubmissionResult {
+    save_submission(store, context, history::Action::Edit, submission)
+}
+
+fn issue_submission(
+    store: &store::Store,
+    context: &Context,
+    submission: slack::dialog::Submission,
+) -> SubmissionResult {
+    save_submission(store, context, history::Action::Issue, submission)
+}
+
+/// Shown when the entry a dialog was opened on has been deleted since.
+const DELETED_ERROR: &str = "다른 사용자가 삭제했습니다. 창을 닫고 다시 열어 주세요.";
+/// Shown when the add dialog is submitted with an IP that is stored already.
+const EXISTS_ERROR: &str = "이미 등록된 IP입니다.";
+
+fn save_submission(
+    store: &store::Store,
+    context: &Context,
+    action: history::Action,
+    submission: slack::dialog::Submission,
+) -> SubmissionResult {
+    use history::Event;
+    use ip::Entry;
+    use slack::dialog::{SubmissionError, SubmissionErrors};
+    let opened = submission
+        .state
+        .as_ref()
+        .and_then(|s| parse_entry_state(s));
+    let fields = &context.custom_fields;
+    let mut entry: Entry = match submission.submission.into_entry(fields) {
+        Ok(entry) => entry,
+        Err(errors) => return Ok(Some(errors)),
+    };
+    if entry.owner.is_none() {
+        entry.owner = Some(submission.user.id.clone());
+    }
+    // The custom fields share one textarea, so a select field is only held
+    // to its options here.
+    for field in fields {
+        if let Some(value) = entry.custom.get(&field.name) {
+            if let Err(e) = field.validate(value) {
+                return Ok(Some(SubmissionErrors {
+                    errors: vec![SubmissionError {
+                        name: "details".to_owned(),
+                        error: e,
+                    }],
+                }));
+            }
+        }
+    }
+    // A MAC address identifies one device, so it can't be on two entries.
+    for mac in &entry.macs {
+        if let Some(other) = store.with_mac(mac) {
+            if other.ip != entry.ip {
+                return Ok(Some(SubmissionErrors {
+                    errors: vec![SubmissionError {
+                        name: "macs".to_owned(),
+                        error: format!("{} 는 이미 {} 에 등록되어 있습니다.", mac, other.ip),
+                    }],
+                }));
+            }
+        }
+    }
+    let before = store.get(&entry.ip.to_string());
+    let ip_error = match (opened.as_ref(), before.as_ref()) {
+        (Some(&(ref ip, _)), _) if *ip != entry.ip.to_string() => {
+            Some("IP 주소는 바꿀 수 없습니다. 새 IP는 /ip-add 로 추가해 주세요.")
+        }
+        (Some(_), None) => Some(DELETED_ERROR),
+        (None, Some(_)) => Some(EXISTS_ERROR),
+        _ => None,
+    };
+    if let Some(error) = ip_error {
+        return Ok(Some(SubmissionErrors {
+            errors: vec![SubmissionError {
+                name: "ip".to_owned(),
+                error: error.to_owned(),
+            }],
+        }));
+    }
+    if let Some(ref before) = before {
+        // An expired lease is issued like an available entry.
+        let current = if before.is_lease_expired(ip::today()) {
+            status::Status::Available
+        } else {
+            before.status
+        };
+        if !current.can_become(entry.status) {
+            return Ok(Some(SubmissionErrors {
+                errors: vec![SubmissionError {
+                    name: "status".to_owned(),
+                    error: format!(
+                        "{} 상태에서 {} 상태로 바꿀 수 없습니다.",
+                        current.label(),
+                        entry.status.label()
+                    ),
+                }],
+            }));
+        }
+    }
+    // Values of fields removed from the settings aren't in the dialog, so
+    // they're carried over instead of being dropped.
+    if let Some(ref before) = before {
+        for (name, value) in &before.custom {
+            if !fields.iter().any(|f| &f.name == name) {
+                entry.custom.insert(name.clone(), value.clone());
+            }
+        }
+    }
+    // Dialogs opened on an existing entry carry the revision they were opened
+    // on, so a concurrent change isn't silently overwritten. The checks above
+    // are repeated by the store, as the entry may change in the meantime.
+    let saved = match opened {
+        Some((_, revision)) => store.update(&entry, revision),
+        None => store.create(&entry),
+    };
+    let error = match saved {
+        Ok(saved) => {
+            store.record(&Event::new(
+                action,
+                &submission.user,
+                before.as_ref(),
+                Some(&saved),
+            )?)?;
+            return Ok(None);
+        }
+        Err(ref e) if is_stale(e) => "다른 사용자가 먼저 수정했습니다. 창을 닫고 다시 열어 주세요.",
+        Err(ref e) if is_not_found(e) => DELETED_ERROR,
+        Err(ref e) if is_exists(e) => EXISTS_ERROR,
+        Err(e) => return Err(e),
+    };
+    Ok(Some(SubmissionErrors {
+        errors: vec![SubmissionError {
+            name: "ip".to_owned(),
+            error: error.to_owned(),
+        }],
+    }))
+}
+
+/// The state of a dialog opened on an existing entry: the IP it was opened on
+/// and its revision at the time.
+fn entry_state(entry: &ip::Entry) -> String {
+    format!("{} {}", entry.ip, entry.revision)
+}
+
+fn parse_entry_state(state: &str) -> Option<(String, u64)> {
+    let mut split = state.splitn(2, ' ');
+    let ip = split.next()?.to_owned();
+    let revision = split.next()?.parse().ok()?;
+    Some((ip, revision))
+}
+
+fn is_stale(e: &error::Error) -> bool {
+    match *e.kind() {
+        ErrorKind::StaleRevision(_) => true,
+        _ => false,
+    }
+}
+
+fn is_not_found(e: &error::Error) -> bool {
+    match *e.kind() {
+        ErrorKind::EntryNotFound(_) => true,
+        _ => false,
+    }
+}
+
+fn is_exists(e: &error::Error) -> bool {
+    match *e.kind() {
+        ErrorKind::EntryExists(_) => true,
+        _ => false,
+    }
+}
+
+fn is_not_supported(e: &error::Error) -> bool {
+    match *e.kind() {
+        ErrorKind::NotSupported(_) => true,
+        _ => false,
+    }
+}
+
+fn generate_get_message(
+    entry: ip::Entry,
+    subnet: Option<&subnet::Subnet>,
+    fields: &[custom::CustomField],
+) -> slack::AttachedMessage {
     use slack::*;
     let mut m = AttachedMessage {
         attachments: vec![],
@@ -215,26 +755,52 @@ fn generate_get_message(entry: ip::Entry) -> slack::AttachedMessage {
     let mut a = Attachment {
         title: format!("IP {}의 정보", entry.ip),
         fields: vec![],
+        callback_id: None,
+        actions: vec![],
     };
     let joined_ports = entry.ports_as_string();
+    let joined_macs = entry.macs_as_string();
+    let lease_expired = entry.is_lease_expired(ip::today());
 
     a.fields.push(AttachmentFields {
         title: "IP".to_owned(),
-        value: entry.ip,
+        value: entry.ip.to_string(),
     });
-    if let Some(domain) = entry.domain {
+    if let std::net::IpAddr::V6(ref v6) = entry.ip {
+        a.fields.push(AttachmentFields {
+            title: "IPv6 전체 표기".to_owned(),
+            value: ip::expanded(v6),
+        });
+    }
+    if let Some(subnet) = subnet {
+        a.fields.push(AttachmentFields {
+            title: "서브넷".to_owned(),
+            value: format!("{} ({})", subnet.name, subnet.cidr),
+        });
+    }
+    if !entry.dns_names.is_empty() {
         a.fields.push(AttachmentFields {
-            title: "도메인".to_owned(),
-            value: domain,
+            title: "DNS 이름".to_owned(),
+            value: entry
+                .dns_names
+                .iter()
+                .map(|n| {
+                    let mut details = vec![n.record.as_str().to_owned()];
+                    if let Some(ttl) = n.ttl {
+                        details.push(format!("TTL {}", ttl));
+                    }
+                    if n.primary {
+                        details.push("대표".to_owned());
+                    }
+                    format!("{} ({})", n.name, details.join(", "))
+                })
+                .collect::<Vec<String>>()
+                .join("\n"),
         });
     }
     a.fields.push(AttachmentFields {
-        title: "사용 여부".to_owned(),
-        value: if entry.using {
-            "사용중"
-        } else {
-            "미사용"
-        }.to_owned(),
+        title: "상태".to_owned(),
+        value: entry.status.label().to_owned(),
     });
 
     if !entry.open_ports.is_empty() {
@@ -249,27 +815,132 @@ fn generate_get_message(entry: ip::Entry) -> slack::AttachedMessage {
             value: description,
         });
     }
+    if !joined_macs.is_empty() {
+        a.fields.push(AttachmentFields {
+            title: "MAC 주소".to_owned(),
+            value: joined_macs,
+        });
+    }
+    for &(title, ref value) in &[
+        ("호스트명", &entry.hostname),
+        ("모델", &entry.model),
+        ("위치", &entry.location),
+    ] {
+        if let Some(ref value) = **value {
+            a.fields.push(AttachmentFields {
+                title: title.to_owned(),
+                value: value.clone(),
+            });
+        }
+    }
+    // Fields of the settings first in their order, then the values of fields
+    // removed from it under their name.
+    for field in fields {
+        if let Some(value) = entry.custom.get(&field.name) {
+            a.fields.push(AttachmentFields {
+                title: field.label.clone(),
+                value: value.clone(),
+            });
+        }
+    }
+    for (name, value) in &entry.custom {
+        if !fields.iter().any(|f| &f.name == name) {
+            a.fields.push(AttachmentFields {
+                title: name.clone(),
+                value: value.clone(),
+            });
+        }
+    }
+    if let Some(lease_expires) = entry.lease_expires {
+        a.fields.push(AttachmentFields {
+            title: "임대 만료일".to_owned(),
+            value: if lease_expired {
+                format!("{} (만료됨)", lease_expires)
+            } else {
+                lease_expires.to_string()
+            },
+        });
+    }
+    if !entry.tags.is_empty() {
+        a.fields.push(AttachmentFields {
+            title: "태그".to_owned(),
+            value: entry.tags.join(", "),
+        });
+    }
+    if let Some(owner) = entry.owner {
+        a.fields.push(AttachmentFields {
+            title: "담당자".to_owned(),
+            value: format!("<@{}>", owner),
+        });
+    }
+    if let Some(modified) = entry.modified {
+        a.fields.push(AttachmentFields {
+            title: "마지막 수정".to_owned(),
+            value: modified
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+        });
+    }
     m.attachments.push(a);
     m
 }
 
-fn generate_list_message(query: &str, queries: Vec<ip::Query>) -> slack::AttachedMessage {
+fn generate_list_message(
+    query: &str,
+    queries: Vec<ip::Query>,
+    offset: usize,
+) -> slack::AttachedMessage {
     use slack::*;
+    use slack::interactive::Action;
     let mut m = AttachedMessage {
         attachments: vec![],
     };
+    let total = queries.len();
+    let title = if query.is_empty() {
+        "IP 목록".to_owned()
+    } else {
+        format!("{}의 검색 결과", query)
+    };
     let mut a = Attachment {
-        title: if query.is_empty() {
-            "IP 목록".to_owned()
+        title: if total > PAGE_SIZE {
+            format!(
+                "{} (총 {}개 중 {}-{})",
+                title,
+                total,
+                offset + 1,
+                total.min(offset + PAGE_SIZE)
+            )
         } else {
-            format!("{}의 검색 결과", query)
+            format!("{} (총 {}개)", title, total)
         },
         fields: vec![],
+        callback_id: None,
+        actions: vec![],
     };
+    if offset > 0 {
+        let previous = offset.saturating_sub(PAGE_SIZE);
+        a.actions
+            .push(Action::button("previous", "이전", format!("{} {}", previous, query)));
+    }
+    if offset + PAGE_SIZE < total {
+        let next = offset + PAGE_SIZE;
+        a.actions
+            .push(Action::button("next", "다음", format!("{} {}", next, query)));
+    }
+    if !a.actions.is_empty() {
+        a.callback_id = Some("list".to_owned());
+    }
 
-    for q in queries {
+    for q in queries.into_iter().skip(offset).take(PAGE_SIZE) {
+        // IPv6 addresses don't always look like one once compressed.
+        let title = if q.ip.contains(':') {
+            format!("{} (IPv6)", q.ip)
+        } else {
+            q.ip
+        };
         a.fields.push(AttachmentFields {
-            title: q.ip,
+            title,
             value: q.element,
         });
     }
@@ -277,6 +948,216 @@ fn generate_list_message(query: &str, queries: Vec<ip::Query>) -> slack::Attache
     m
 }
 
+fn generate_history_message(ip: &str, events: Vec<history::Event>) -> slack::AttachedMessage {
+    use slack::*;
+    let mut m = AttachedMessage {
+        attachments: vec![],
+    };
+    let mut a = Attachment {
+        title: format!("IP {}의 변경 기록", ip),
+        fields: vec![],
+        callback_id: None,
+        actions: vec![],
+    };
+
+    for e in events {
+        let mut value = format!("<@{}>", e.user.id);
+        if e.changes.is_empty() {
+            value.push_str("\n변경 없음");
+        }
+        for c in &e.changes {
+            value.push_str(&format!("\n{}", c));
+        }
+        a.fields.push(AttachmentFields {
+            title: format!("{} {}", e.local_time(), e.action.label()),
+            value,
+        });
+    }
+    m.attachments.push(a);
+    m
+}
+
+fn generate_trash_message(trashed: Vec<store::Trashed>) -> slack::AttachedMessage {
+    use slack::*;
+    let mut m = AttachedMessage {
+        attachments: vec![],
+    };
+    let mut a = Attachment {
+        title: "휴지통".to_owned(),
+        fields: vec![],
+        callback_id: None,
+        actions: vec![],
+    };
+
+    for t in trashed {
+        let mut value = format!(
+            "{} <@{}> 삭제",
+            t.deleted_at
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M"),
+            t.deleted_by.id
+        );
+        if let Some(name) = t.entry.primary_name() {
+            value.push_str("\n");
+            value.push_str(&name.name);
+        }
+        a.fields.push(AttachmentFields {
+            title: t.entry.ip.to_string(),
+            value,
+        });
+    }
+    m.attachments.push(a);
+    m
+}
+
+fn generate_leases_message(
+    days: i64,
+    today: chrono::NaiveDate,
+    leases: &[&ip::Entry],
+) -> slack::AttachedMessage {
+    use slack::*;
+    let mut m = AttachedMessage {
+        attachments: vec![],
+    };
+    let mut a = Attachment {
+        title: format!("만료되었거나 {}일 안에 만료되는 임대", days),
+        fields: vec![],
+        callback_id: None,
+        actions: vec![],
+    };
+
+    for e in leases {
+        let expires = match e.lease_expires {
+            Some(d) => d,
+            None => continue,
+        };
+        let left = expires.signed_duration_since(today).num_days();
+        let mut value = if left < 0 {
+            format!("{}일 전 만료됨", -left)
+        } else if left == 0 {
+            "오늘 만료".to_owned()
+        } else {
+            format!("{}일 남음", left)
+        };
+        if let Some(ref owner) = e.owner {
+            value.push_str(&format!("\n<@{}>", owner));
+        }
+        a.fields.push(AttachmentFields {
+            title: format!("{} ({})", e.ip, expires),
+            value,
+        });
+    }
+    m.attachments.push(a);
+    m
+}
+
+fn generate_subnet_list_message(
+    subnets: &[subnet::Subnet],
+    entries: &[ip::Entry],
+) -> slack::AttachedMessage {
+    use slack::*;
+    let mut m = AttachedMessage {
+        attachments: vec![],
+    };
+    let mut a = Attachment {
+        title: "서브넷 목록".to_owned(),
+        fields: vec![],
+        callback_id: None,
+        actions: vec![],
+    };
+
+    for s in subnets {
+        let usage = s.usage(entries);
+        a.fields.push(AttachmentFields {
+            title: format!("{} ({})", s.name, s.cidr),
+            value: format!(
+                "할당 {} / 미사용 {} / 예약 {}",
+                usage.used, usage.free, usage.reserved
+            ),
+        });
+    }
+    m.attachments.push(a);
+    m
+}
+
+fn generate_subnet_message(subnet: &subnet::Subnet, entries: &[ip::Entry]) -> slack::AttachedMessage {
+    use slack::*;
+    let mut m = AttachedMessage {
+        attachments: vec![],
+    };
+    let mut a = Attachment {
+        title: format!("서브넷 {}의 정보", subnet.name),
+        fields: vec![],
+        callback_id: None,
+        actions: vec![],
+    };
+    let usage = subnet.usage(entries);
+
+    a.fields.push(AttachmentFields {
+        title: "네트워크".to_owned(),
+        value: subnet.cidr.to_string(),
+    });
+    if let Some(gateway) = subnet.gateway {
+        a.fields.push(AttachmentFields {
+            title: "게이트웨이".to_owned(),
+            value: gateway.to_string(),
+        });
+    }
+    if let Some(vlan) = subnet.vlan {
+        a.fields.push(AttachmentFields {
+            title: "VLAN".to_owned(),
+            value: vlan.to_string(),
+        });
+    }
+    if let Some(ref description) = subnet.description {
+        a.fields.push(AttachmentFields {
+            title: "설명".to_owned(),
+            value: description.clone(),
+        });
+    }
+    a.fields.push(AttachmentFields {
+        title: "주소 수".to_owned(),
+        value: format!(
+            "전체 {} / 할당 {} / 미사용 {} / 예약 {}",
+            usage.total, usage.used, usage.free, usage.reserved
+        ),
+    });
+    m.attachments.push(a);
+    m
+}
+
+fn generate_fsck_message(report: store::FsckReport) -> slack::AttachedMessage {
+    use slack::*;
+    let mut m = AttachedMessage {
+        attachments: vec![],
+    };
+    let mut a = Attachment {
+        title: format!(
+            "데이터 검사 결과: {}개 중 {}개의 문제",
+            report.checked,
+            report.problems.len()
+        ),
+        fields: vec![],
+        callback_id: None,
+        actions: vec![],
+    };
+
+    for p in report.problems {
+        a.fields.push(AttachmentFields {
+            title: match p {
+                store::Problem::Stray(..) => "알 수 없는 파일",
+                store::Problem::Unreadable(..) => "읽을 수 없음",
+                store::Problem::Invalid(..) => "형식 오류",
+                store::Problem::Mismatch(..) => "이름 불일치",
+                store::Problem::Duplicate(..) => "중복",
+            }.to_owned(),
+            value: p.to_string(),
+        });
+    }
+    m.attachments.push(a);
+    m
+}
+
 fn show_dialog(token: &str, dialog: slack::dialog::Dialog, trigger_id: &str) -> Result<()> {
     let request = slack::dialog::OpenRequest {
         token: token.to_owned(),
@@ -287,27 +1168,51 @@ fn show_dialog(token: &str, dialog: slack::dialog::Dialog, trigger_id: &str) ->
     Ok(())
 }
 
-fn generate_add_dialog() -> slack::dialog::Dialog {
+fn generate_add_dialog(fields: &[custom::CustomField]) -> slack::dialog::Dialog {
     let mut dialog = slack::dialog::Dialog::new("add".to_owned(), "IP 추가".to_owned());
 
     dialog.elements.push(generate_ip_text(None));
-    dialog.elements.push(generate_domain_text(None));
-    dialog.elements.push(generate_using_select(None));
+    dialog.elements.push(generate_dns_names_textarea(None));
+    dialog.elements.push(generate_status_select(None));
     dialog.elements.push(generate_open_ports_text(None));
     dialog.elements.push(generate_description_textarea(None));
+    dialog.elements.push(generate_tags_text(None));
+    dialog.elements.push(generate_owner_select(None));
+    dialog.elements.push(generate_lease_expires_text(None));
+    dialog.elements.push(generate_macs_text(None));
+    dialog.elements.push(generate_details_textarea(None, fields));
 
     dialog
 }
 
-fn generate_edit_dialog(entry: ip::Entry) -> slack::dialog::Dialog {
-    let mut dialog = slack::dialog::Dialog::new("edit".to_owned(), "IP 수정".to_owned());
+fn generate_edit_dialog(entry: ip::Entry, fields: &[custom::CustomField]) -> slack::dialog::Dialog {
+    generate_entry_dialog("edit", "IP 수정", entry, fields)
+}
+
+fn generate_issue_dialog(entry: ip::Entry, fields: &[custom::CustomField]) -> slack::dialog::Dialog {
+    generate_entry_dialog("issue", "IP 발급", entry, fields)
+}
+
+fn generate_entry_dialog(
+    callback_id: &str,
+    title: &str,
+    entry: ip::Entry,
+    fields: &[custom::CustomField],
+) -> slack::dialog::Dialog {
+    let mut dialog = slack::dialog::Dialog::new(callback_id.to_owned(), title.to_owned());
+    dialog.state = Some(entry_state(&entry));
     let joined_ports = entry.ports_as_string();
+    let joined_macs = entry.macs_as_string();
+    let details = entry.details_as_string(fields);
+    let names = entry.names_as_string();
 
-    dialog.elements.push(generate_ip_text(Some(entry.ip)));
-    dialog.elements.push(generate_domain_text(entry.domain));
-    dialog.elements.push(generate_using_select(
-        Some(if entry.using { "true" } else { "false" }.to_owned()),
-    ));
+    dialog.elements.push(generate_ip_text(Some(entry.ip.to_string())));
+    dialog.elements.push(generate_dns_names_textarea(if names.is_empty() {
+        None
+    } else {
+        Some(names)
+    }));
+    dialog.elements.push(generate_status_select(Some(entry.status)));
     dialog.elements.push(generate_open_ports_text({
         if !entry.open_ports.is_empty() {
             Some(joined_ports)
@@ -318,6 +1223,28 @@ fn generate_edit_dialog(entry: ip::Entry) -> slack::dialog::Dialog {
     dialog
         .elements
         .push(generate_description_textarea(entry.description));
+    dialog.elements.push(generate_tags_text(if entry.tags.is_empty() {
+        None
+    } else {
+        Some(entry.tags.join(", "))
+    }));
+    dialog.elements.push(generate_owner_select(entry.owner));
+    dialog
+        .elements
+        .push(generate_lease_expires_text(entry.lease_expires.map(|d| d.to_string())));
+    dialog.elements.push(generate_macs_text(if joined_macs.is_empty() {
+        None
+    } else {
+        Some(joined_macs)
+    }));
+    dialog.elements.push(generate_details_textarea(
+        if details.is_empty() {
+            None
+        } else {
+            Some(details)
+        },
+        fields,
+    ));
 
     dialog
 }
@@ -335,36 +1262,39 @@ fn generate_ip_text(value: Option<String>) -> slack::dialog::element::Element {
     })
 }
 
-fn generate_domain_text(value: Option<String>) -> slack::dialog::element::Element {
-    use slack::dialog::element::{Element, TextElement};
-    Element::Text(TextElement {
-        label: "도메인".to_owned(),
-        name: "domain".to_owned(),
+fn generate_dns_names_textarea(value: Option<String>) -> slack::dialog::element::Element {
+    use slack::dialog::element::{Element, TextAreaElement};
+    Element::TextArea(TextAreaElement {
+        label: "DNS 이름".to_owned(),
+        name: "dns_names".to_owned(),
         optional: Some(true),
-        hint: None,
+        hint: Some(
+            "한 줄에 하나씩, 이름 뒤에 레코드 종류(A, AAAA, CNAME, PTR), TTL, primary를 붙일 수 있음"
+                .to_owned(),
+        ),
         subtype: None,
         value,
-        placeholder: Some("Optional".to_owned()),
+        placeholder: Some("www.example.com A 300 primary\nalias.example.com CNAME".to_owned()),
     })
 }
 
-fn generate_using_select(value: Option<String>) -> slack::dialog::element::Element {
+/// Offers only the statuses the entry may be changed to from `current`.
+fn generate_status_select(current: Option<status::Status>) -> slack::dialog::element::Element {
     use slack::dialog::element::{Element, SelectElement, SelectOption};
     Element::Select(SelectElement {
-        label: "사용 여부".to_owned(),
-        name: "using".to_owned(),
+        label: "상태".to_owned(),
+        name: "status".to_owned(),
         optional: None,
-        options: vec![
-            SelectOption {
-                label: "사용중".to_owned(),
-                value: "true".to_owned(),
-            },
-            SelectOption {
-                label: "미사용".to_owned(),
-                value: "false".to_owned(),
-            },
-        ],
-        value,
+        data_source: None,
+        options: status::ALL
+            .iter()
+            .filter(|s| current.map_or(true, |c| c.can_become(**s)))
+            .map(|s| SelectOption {
+                label: s.label().to_owned(),
+                value: s.as_str().to_owned(),
+            })
+            .collect(),
+        value: current.map(|s| s.as_str().to_owned()),
         placeholder: None,
     })
 }
@@ -375,10 +1305,107 @@ fn generate_open_ports_text(value: Option<String>) -> slack::dialog::element::El
         label: "개방된 포트".to_owned(),
         name: "open_ports".to_owned(),
         optional: Some(true),
+        hint: Some("쉼표로 구분, 프로토콜과 범위 지정 가능".to_owned()),
+        subtype: None,
+        value,
+        placeholder: Some("ex) 22, 53/udp, 8000-8100/tcp".to_owned()),
+    })
+}
+
+fn generate_tags_text(value: Option<String>) -> slack::dialog::element::Element {
+    use slack::dialog::element::{Element, TextElement};
+    Element::Text(TextElement {
+        label: "태그".to_owned(),
+        name: "tags".to_owned(),
+        optional: Some(true),
+        hint: Some("쉼표로 구분, /ip-list tag:<태그> 로 검색".to_owned()),
+        subtype: None,
+        value,
+        placeholder: Some("ex) web, production".to_owned()),
+    })
+}
+
+fn generate_lease_expires_text(value: Option<String>) -> slack::dialog::element::Element {
+    use slack::dialog::element::{Element, TextElement};
+    Element::Text(TextElement {
+        label: "임대 만료일".to_owned(),
+        name: "lease_expires".to_owned(),
+        optional: Some(true),
+        hint: Some("이 날이 지나면 다른 사람에게 발급될 수 있습니다.".to_owned()),
+        subtype: None,
+        value,
+        placeholder: Some("YYYY-MM-DD".to_owned()),
+    })
+}
+
+fn generate_macs_text(value: Option<String>) -> slack::dialog::element::Element {
+    use slack::dialog::element::{Element, TextElement};
+    Element::Text(TextElement {
+        label: "MAC 주소".to_owned(),
+        name: "macs".to_owned(),
+        optional: Some(true),
         hint: Some("쉼표로 구분".to_owned()),
         subtype: None,
         value,
-        placeholder: Some("ex) 22, 80".to_owned()),
+        placeholder: Some("ex) aa:bb:cc:dd:ee:ff".to_owned()),
+    })
+}
+
+/// The textarea of the hardware details and the custom fields, which don't
+/// fit in the dialog as elements of their own.
+fn generate_details_textarea(
+    value: Option<String>,
+    fields: &[custom::CustomField],
+) -> slack::dialog::element::Element {
+    use slack::dialog::element::{Element, TextAreaElement};
+    let mut keys = vec![
+        "hostname".to_owned(),
+        "model".to_owned(),
+        "location".to_owned(),
+    ];
+    keys.extend(fields.iter().map(|f| format!("{}({})", f.name, f.label)));
+    Element::TextArea(TextAreaElement {
+        label: "추가 정보".to_owned(),
+        name: "details".to_owned(),
+        optional: Some(true),
+        hint: Some(generate_details_hint(&keys)),
+        subtype: None,
+        value,
+        placeholder: Some("hostname: web-01\nmodel: PowerEdge R640\nlocation: 3층 서버실".to_owned()),
+    })
+}
+
+/// The longest hint Slack accepts, in characters.
+const HINT_LIMIT: usize = 150;
+
+/// Lists as many of `keys` as fit in a hint. The options of a select field
+/// are left to the error shown when a value isn't one of them.
+fn generate_details_hint(keys: &[String]) -> String {
+    let mut hint = "한 줄에 하나씩 '키: 값' 형식으로: ".to_owned();
+    let more = " 등";
+    for (i, key) in keys.iter().enumerate() {
+        let separator = if i == 0 { "" } else { ", " };
+        let len = hint.chars().count() + separator.chars().count() + key.chars().count();
+        if len + more.chars().count() > HINT_LIMIT {
+            hint.push_str(more);
+            break;
+        }
+        hint.push_str(separator);
+        hint.push_str(key);
+    }
+    hint
+}
+
+fn generate_owner_select(value: Option<String>) -> slack::dialog::element::Element {
+    use slack::dialog::element::{Element, SelectElement};
+    Element::Select(SelectElement {
+        label: "담당자".to_owned(),
+        name: "owner".to_owned(),
+        optional: Some(true),
+        options: vec![],
+        data_source: Some("users".to_owned()),
+        value,
+        placeholder: Some("비워 두면 제출한 사람".to_owned()),
     })
 }
 
@@ -394,3 +1421,202 @@ fn generate_description_textarea(value: Option<String>) -> slack::dialog::elemen
         placeholder: Some("Optional".to_owned()),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use store::{MemoryStore, Store};
+
+    fn context() -> Context {
+        Context {
+            custom_fields: vec![],
+            subnets: vec![],
+            trash_purge_days: 30,
+        }
+    }
+
+    fn user() -> slack::User {
+        slack::User {
+            id: "U024BE7LH".to_owned(),
+            name: "alice".to_owned(),
+        }
+    }
+
+    fn submission(
+        callback_id: &str,
+        ip: &str,
+        description: &str,
+        state: Option<String>,
+    ) -> slack::dialog::Submission {
+        serde_json::from_value(json!({
+            "type": "dialog_submission",
+            "submission": {
+                "ip": ip,
+                "status": "in-use",
+                "open_ports": "22",
+                "description": description,
+            },
+            "callback_id": callback_id,
+            "team": { "id": "T024BE7LD", "domain": "example" },
+            "user": { "id": "U024BE7LH", "name": "alice" },
+            "channel": { "id": "C024BE7LR", "name": "general" },
+            "action_ts": "1500000000.000000",
+            "token": "token",
+            "state": state,
+        })).unwrap()
+    }
+
+    /// Submits the add dialog, returning the errors shown in it.
+    fn add(store: &Store, ip: &str, description: &str) -> Vec<String> {
+        errors(add_submission(
+            store,
+            &context(),
+            submission("add", ip, description, None),
+        ))
+    }
+
+    /// Submits an edit dialog opened with `state`, returning the errors shown
+    /// in it.
+    fn edit(store: &Store, ip: &str, description: &str, state: &str) -> Vec<String> {
+        errors(edit_submission(
+            store,
+            &context(),
+            submission("edit", ip, description, Some(state.to_owned())),
+        ))
+    }
+
+    fn errors(result: SubmissionResult) -> Vec<String> {
+        result
+            .unwrap()
+            .map(|e| e.errors.into_iter().map(|e| e.error).collect())
+            .unwrap_or_default()
+    }
+
+    fn text(response: Result<Response>) -> String {
+        match response.unwrap() {
+            Response::PlainText(t) => t,
+            _ => panic!("not a plain text response"),
+        }
+    }
+
+    fn description(store: &Store, ip: &str) -> Option<String> {
+        store.get(ip).and_then(|e| e.description)
+    }
+
+    #[test]
+    fn add_entry() {
+        let store = MemoryStore::new();
+        assert!(add(&store, "10.0.0.1", "web").is_empty());
+        let entry = store.get("10.0.0.1").unwrap();
+        assert_eq!(entry.revision, 0);
+        assert_eq!(entry.description, Some("web".to_owned()));
+        assert_eq!(entry.owner, Some("U024BE7LH".to_owned()));
+        assert_eq!(store.history("10.0.0.1").len(), 1);
+    }
+
+    #[test]
+    fn add_existing_entry() {
+        let store = MemoryStore::new();
+        add(&store, "10.0.0.1", "web");
+        assert_eq!(add(&store, "10.0.0.1", "db"), vec![EXISTS_ERROR]);
+        assert_eq!(description(&store, "10.0.0.1"), Some("web".to_owned()));
+    }
+
+    #[test]
+    fn edit_entry() {
+        let store = MemoryStore::new();
+        add(&store, "10.0.0.1", "web");
+        let state = entry_state(&store.get("10.0.0.1").unwrap());
+        assert!(edit(&store, "10.0.0.1", "db", &state).is_empty());
+        assert_eq!(store.get("10.0.0.1").unwrap().revision, 1);
+        assert_eq!(description(&store, "10.0.0.1"), Some("db".to_owned()));
+    }
+
+    #[test]
+    fn edit_stale_revision() {
+        let store = MemoryStore::new();
+        add(&store, "10.0.0.1", "web");
+        let state = entry_state(&store.get("10.0.0.1").unwrap());
+        edit(&store, "10.0.0.1", "db", &state);
+        assert_eq!(edit(&store, "10.0.0.1", "mail", &state).len(), 1);
+        assert_eq!(description(&store, "10.0.0.1"), Some("db".to_owned()));
+    }
+
+    #[test]
+    fn edit_changed_ip() {
+        let store = MemoryStore::new();
+        add(&store, "10.0.0.1", "web");
+        let state = entry_state(&store.get("10.0.0.1").unwrap());
+        assert_eq!(edit(&store, "10.0.0.2", "web", &state).len(), 1);
+        assert!(store.get("10.0.0.2").is_none());
+    }
+
+    #[test]
+    fn edit_deleted_entry() {
+        let store = MemoryStore::new();
+        add(&store, "10.0.0.1", "web");
+        let state = entry_state(&store.get("10.0.0.1").unwrap());
+        del_command(&store, &context(), &user(), "10.0.0.1").unwrap();
+        assert_eq!(edit(&store, "10.0.0.1", "db", &state), vec![DELETED_ERROR]);
+        assert!(store.get("10.0.0.1").is_none());
+        assert_eq!(
+            text(restore_command(&store, &user(), "10.0.0.1")),
+            "IP 10.0.0.1 restored"
+        );
+    }
+
+    #[test]
+    fn del_and_restore() {
+        let store = MemoryStore::new();
+        add(&store, "10.0.0.1", "web");
+        assert_eq!(
+            text(del_command(&store, &context(), &user(), "10.0.0.1")),
+            "IP 10.0.0.1 moved to trash"
+        );
+        assert!(store.get("10.0.0.1").is_none());
+        assert_eq!(store.trash().len(), 1);
+        assert_eq!(
+            text(del_command(&store, &context(), &user(), "10.0.0.1")),
+            "IP not found"
+        );
+        assert_eq!(
+            text(restore_command(&store, &user(), "10.0.0.1")),
+            "IP 10.0.0.1 restored"
+        );
+        assert_eq!(description(&store, "10.0.0.1"), Some("web".to_owned()));
+        assert_eq!(
+            text(restore_command(&store, &user(), "10.0.0.1")),
+            "IP not found in trash"
+        );
+    }
+
+    #[test]
+    fn restore_over_existing_entry() {
+        let store = MemoryStore::new();
+        add(&store, "10.0.0.1", "web");
+        del_command(&store, &context(), &user(), "10.0.0.1").unwrap();
+        add(&store, "10.0.0.1", "db");
+        assert_eq!(
+            text(restore_command(&store, &user(), "10.0.0.1")),
+            "IP 10.0.0.1 already exists, delete it first"
+        );
+        assert_eq!(description(&store, "10.0.0.1"), Some("db".to_owned()));
+    }
+
+    #[test]
+    fn list() {
+        let store = MemoryStore::new();
+        add(&store, "10.0.0.10", "web");
+        add(&store, "10.0.0.2", "db");
+        let message = match list_command(&store, &context(), "port:22").unwrap() {
+            Response::AttachedMessage(m) => serde_json::to_string(&m).unwrap(),
+            _ => panic!("not an attached message"),
+        };
+        assert!(message.find("10.0.0.2").unwrap() < message.find("10.0.0.10").unwrap());
+        assert_eq!(
+            text(list_command(&store, &context(), "port:80")),
+            "IP not found"
+        );
+        assert!(text(list_command(&store, &context(), "(port:22")).starts_with("Invalid query"));
+    }
+}
@@ -0,0 +1,64 @@
+//! MAC addresses, written as `aa:bb:cc:dd:ee:ff`.
+
+extern crate serde;
+
+use std::fmt;
+use std::str::FromStr;
+
+use self::serde::de::{Deserialize, Deserializer, Error};
+use self::serde::ser::{Serialize, Serializer};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl FromStr for MacAddr {
+    type Err = String;
+
+    /// Accepts the usual notations: `aa:bb:cc:dd:ee:ff`, `AA-BB-CC-DD-EE-FF`,
+    /// `aabb.ccdd.eeff` and `aabbccddeeff`.
+    fn from_str(s: &str) -> Result<MacAddr, String> {
+        let invalid = || format!("Invalid MAC address: {}", s);
+        let s = s.trim();
+        let digits: String = match s.len() {
+            17 if s.split(|c| c == ':' || c == '-').all(|g| g.len() == 2) => {
+                s.split(|c| c == ':' || c == '-').collect()
+            }
+            14 if s.split('.').all(|g| g.len() == 4) => s.split('.').collect(),
+            12 => s.to_owned(),
+            _ => return Err(invalid()),
+        };
+        if digits.len() != 12 || !digits.chars().all(|c| c.is_digit(16)) {
+            return Err(invalid());
+        }
+        let mut bytes = [0u8; 6];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(MacAddr(bytes))
+    }
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let b = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            b[0], b[1], b[2], b[3], b[4], b[5]
+        )
+    }
+}
+
+impl Serialize for MacAddr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for MacAddr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MacAddr, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
@@ -6,12 +6,33 @@ extern crate rocket_contrib;
 extern crate serde_json;
 
 use rocket::request::LenientForm;
+use rocket::response::content;
 use ip_manager::Result;
-use ip_manager::{handle_command, handle_submission};
+use ip_manager::{fsck, handle_action, handle_command, handle_submission, import_data_path, migrate};
 use ip_manager::slack::slash_command::Request;
 use ip_manager::slack::dialog::{Submission, SubmissionResponse};
+use ip_manager::slack::interactive::Payload;
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(2).map(|c| c.as_str()) {
+        Some("import") => match import_data_path() {
+            Ok(n) => println!("{} entries imported", n),
+            Err(e) => panic!("Import failed!, {}", e),
+        },
+        Some("migrate") => match migrate() {
+            Ok(n) => println!("{} entries migrated", n),
+            Err(e) => panic!("Migration failed!, {}", e),
+        },
+        Some("fsck") => match fsck(args.get(3).map(|a| a == "--quarantine").unwrap_or(false)) {
+            Ok(report) => print!("{}", report),
+            Err(e) => panic!("Fsck failed!, {}", e),
+        },
+        _ => launch(),
+    }
+}
+
+fn launch() {
     rocket::ignite()
         .mount("/command", routes![command_request])
         .mount("/submission", routes![dialog_response])
@@ -27,10 +48,18 @@ fn command_request(command: String, form: LenientForm<Request>) -> Result<rocket
 }
 
 #[post("/", data = "<form>")]
-fn dialog_response(form: LenientForm<SubmissionResponse>) -> Result<String> {
-    let data: Submission = serde_json::from_str(&form.into_inner().payload).unwrap();
-    handle_submission(data)?;
-    Ok("".to_owned())
+fn dialog_response(form: LenientForm<SubmissionResponse>) -> Result<content::Json<String>> {
+    let payload: serde_json::Value = serde_json::from_str(&form.into_inner().payload)?;
+    // Button clicks are posted here too.
+    if payload["type"] == "interactive_message" {
+        let data: Payload = serde_json::from_value(payload)?;
+        return Ok(content::Json(serde_json::to_string(&handle_action(data)?)?));
+    }
+    let data: Submission = serde_json::from_value(payload)?;
+    match handle_submission(data)? {
+        Some(errors) => Ok(content::Json(serde_json::to_string(&errors)?)),
+        None => Ok(content::Json("".to_owned())),
+    }
 }
 
 #[post("/")]
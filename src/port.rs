@@ -0,0 +1,207 @@
+//! Open port specifications like `22`, `53/udp` or `8000-8100/tcp`.
+
+extern crate serde;
+
+use std::fmt;
+use std::str::FromStr;
+
+use self::serde::de::{Deserialize, Deserializer, Error};
+use self::serde::ser::{Serialize, Serializer};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Protocol {
+    /// No protocol given; both TCP and UDP.
+    Any,
+    Tcp,
+    Udp,
+}
+
+impl Protocol {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Protocol::Any => "any",
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        }
+    }
+
+    fn matches(&self, other: &Protocol) -> bool {
+        *self == Protocol::Any || *other == Protocol::Any || self == other
+    }
+}
+
+/// A single port or an inclusive range of ports, optionally limited to one
+/// protocol.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct PortSpec {
+    pub start: u16,
+    pub end: u16,
+    pub protocol: Protocol,
+}
+
+impl PortSpec {
+    pub fn is_single(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Whether every port of `other` is open in this one, used for the port
+    /// requirements of `issue`.
+    pub fn covers(&self, other: &PortSpec) -> bool {
+        self.protocol.matches(&other.protocol) && self.start <= other.start
+            && other.end <= self.end
+    }
+
+    /// Whether any port of `other` is open in this one, used for searching.
+    pub fn overlaps(&self, other: &PortSpec) -> bool {
+        self.protocol.matches(&other.protocol) && self.start <= other.end
+            && other.start <= self.end
+    }
+}
+
+impl FromStr for PortSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PortSpec, String> {
+        let s = s.trim().to_lowercase();
+        let invalid = || format!("Invalid port: {}", s);
+        let mut parts = s.splitn(2, '/');
+        let range = parts.next().unwrap_or("");
+        let protocol = match parts.next() {
+            None => Protocol::Any,
+            Some("tcp") => Protocol::Tcp,
+            Some("udp") => Protocol::Udp,
+            Some(_) => return Err(invalid()),
+        };
+        let port = |p: &str| match p.trim().parse::<u16>() {
+            Ok(p) if p != 0 => Ok(p),
+            _ => Err(invalid()),
+        };
+        let mut bounds = range.splitn(2, '-');
+        let start = port(bounds.next().unwrap_or(""))?;
+        let end = match bounds.next() {
+            Some(end) => port(end)?,
+            None => start,
+        };
+        if end < start {
+            return Err(invalid());
+        }
+        Ok(PortSpec {
+            start,
+            end,
+            protocol,
+        })
+    }
+}
+
+impl fmt::Display for PortSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_single() {
+            write!(f, "{}", self.start)?;
+        } else {
+            write!(f, "{}-{}", self.start, self.end)?;
+        }
+        match self.protocol {
+            Protocol::Any => Ok(()),
+            p => write!(f, "/{}", p.as_str()),
+        }
+    }
+}
+
+impl Serialize for PortSpec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for PortSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<PortSpec, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Parses a list of specifications separated by `separator`, returning the
+/// ones that couldn't be parsed as the error.
+pub fn parse_list(s: &str, separator: char) -> Result<Vec<PortSpec>, Vec<String>> {
+    let mut specs = vec![];
+    let mut invalid = vec![];
+    for p in s.split(separator).map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        match p.parse() {
+            Ok(spec) => specs.push(spec),
+            Err(_) => invalid.push(p.to_owned()),
+        }
+    }
+    if invalid.is_empty() {
+        Ok(specs)
+    } else {
+        Err(invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(s: &str) -> PortSpec {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            spec("22"),
+            PortSpec {
+                start: 22,
+                end: 22,
+                protocol: Protocol::Any,
+            }
+        );
+        assert_eq!(
+            spec(" 8000-8100/TCP "),
+            PortSpec {
+                start: 8000,
+                end: 8100,
+                protocol: Protocol::Tcp,
+            }
+        );
+        assert_eq!(spec("53/udp").to_string(), "53/udp");
+        assert_eq!(spec("8000-8100").to_string(), "8000-8100");
+        for invalid in &["", "0", "65536", "http", "22/icmp", "100-10", "1-2-3"] {
+            assert!(invalid.parse::<PortSpec>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn covers() {
+        assert!(spec("22").covers(&spec("22")));
+        assert!(spec("22").covers(&spec("22/tcp")));
+        assert!(spec("22/tcp").covers(&spec("22")));
+        assert!(!spec("22/tcp").covers(&spec("22/udp")));
+        assert!(spec("8000-8100").covers(&spec("8000")));
+        assert!(spec("8000-8100").covers(&spec("8050-8100/tcp")));
+        assert!(!spec("8000-8100").covers(&spec("8050-8200")));
+        assert!(!spec("8000").covers(&spec("8000-8001")));
+    }
+
+    #[test]
+    fn overlaps() {
+        assert!(spec("8000-8100").overlaps(&spec("8100-8200")));
+        assert!(spec("8000-8100").overlaps(&spec("7000-9000")));
+        assert!(spec("8050").overlaps(&spec("8000-8100/udp")));
+        assert!(!spec("8000-8100").overlaps(&spec("8101-8200")));
+        assert!(!spec("53/tcp").overlaps(&spec("53/udp")));
+    }
+
+    #[test]
+    fn parse_list_reports_invalid() {
+        assert_eq!(
+            parse_list("22, 80 ,443/tcp,", ','),
+            Ok(vec![spec("22"), spec("80"), spec("443/tcp")])
+        );
+        assert_eq!(
+            parse_list("22 http 0", ' '),
+            Err(vec!["http".to_owned(), "0".to_owned()])
+        );
+    }
+}
@@ -0,0 +1,806 @@
+//! The query language of `/ip-list`.
+//!
+//! Terms next to each other must all match; `OR` and `NOT` (or a leading
+//! `-`) combine them otherwise, and parentheses group them. A term is a word
+//! or a `"quoted phrase"`, optionally limited to one field like `port:22` or
+//! `desc:"web server"`. `*` and `?` in an unquoted word are wildcards, which
+//! match against whole values instead of parts of them. A `sort:` term
+//! sets the order of the results instead of matching anything.
+
+use std::fmt;
+use std::net::IpAddr;
+
+use super::ip::{self, Entry};
+use super::mac::MacAddr;
+use super::port::PortSpec;
+use super::slack;
+use super::sort::Order;
+use super::status::{self, Status};
+use super::subnet::{Cidr, IpRange};
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Field {
+    /// No qualifier; any of the fields below.
+    Any,
+    Ip,
+    Domain,
+    Port,
+    Status,
+    Desc,
+    Tag,
+    Mac,
+    Owner,
+    /// A custom field of the settings, by name.
+    Custom(String),
+}
+
+impl Field {
+    fn from_name(name: &str, custom: &[String]) -> Option<Field> {
+        Some(match name {
+            "ip" => Field::Ip,
+            "domain" | "dns" | "name" => Field::Domain,
+            "port" => Field::Port,
+            "status" => Field::Status,
+            "desc" | "description" => Field::Desc,
+            "tag" => Field::Tag,
+            "mac" => Field::Mac,
+            "owner" => Field::Owner,
+            name if custom.iter().any(|c| c == name) => Field::Custom(name.to_owned()),
+            _ => return None,
+        })
+    }
+
+    /// Fields whose values are matched exactly, so wildcards make no sense.
+    fn is_exact(&self) -> bool {
+        match *self {
+            Field::Port | Field::Status | Field::Mac | Field::Owner => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Term {
+    pub field: Field,
+    pub value: String,
+    /// Whether `value` has wildcards and is matched against whole values.
+    pub wildcard: bool,
+    /// The addresses an address, network or range `value` stands for, which
+    /// are compared numerically instead of as text.
+    pub range: Option<IpRange>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Expr {
+    /// The empty query, matching every entry.
+    All,
+    Term(Term),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    /// Offset of the offending character in the query, counted in characters.
+    pub position: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (at character {})", self.message, self.position + 1)
+    }
+}
+
+fn error<T>(message: String, position: usize) -> Result<T, ParseError> {
+    Err(ParseError { message, position })
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Word {
+        text: String,
+        quoted: bool,
+        /// The qualifier of a quoted phrase, like `desc` in `desc:"a b"`.
+        field: Option<String>,
+    },
+}
+
+fn tokenize(s: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        match chars[i] {
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push((Token::Open, start));
+                i += 1;
+            }
+            ')' => {
+                tokens.push((Token::Close, start));
+                i += 1;
+            }
+            '-' if chars.get(i + 1).map_or(false, |c| !c.is_whitespace()) => {
+                tokens.push((Token::Not, start));
+                i += 1;
+            }
+            '"' => {
+                let (text, end) = phrase(&chars, i)?;
+                tokens.push((
+                    Token::Word {
+                        text,
+                        quoted: true,
+                        field: None,
+                    },
+                    start,
+                ));
+                i = end;
+            }
+            _ => {
+                while i < chars.len() && !chars[i].is_whitespace() && !"()\"".contains(chars[i]) {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                if word.ends_with(':') && chars.get(i) == Some(&'"') {
+                    let (text, end) = phrase(&chars, i)?;
+                    tokens.push((
+                        Token::Word {
+                            text,
+                            quoted: true,
+                            field: Some(word.trim_end_matches(':').to_owned()),
+                        },
+                        start,
+                    ));
+                    i = end;
+                    continue;
+                }
+                let token = match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word {
+                        text: word,
+                        quoted: false,
+                        field: None,
+                    },
+                };
+                tokens.push((token, start));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads the phrase whose opening quote is at `start`, returning it and the
+/// offset after the closing quote.
+fn phrase(chars: &[char], start: usize) -> Result<(String, usize), ParseError> {
+    match chars[start + 1..].iter().position(|c| *c == '"') {
+        Some(0) => error("Empty quotes".to_owned(), start),
+        Some(len) => Ok((
+            chars[start + 1..start + 1 + len].iter().collect(),
+            start + len + 2,
+        )),
+        None => error("Unclosed quote".to_owned(), start),
+    }
+}
+
+/// Whether `word` is a search term of its own even though it has a colon,
+/// like an IPv6 address or a MAC address.
+fn is_plain_value(word: &str) -> bool {
+    addresses(word).is_some() || ip::ipv6_prefix(word).is_some()
+        || word.parse::<MacAddr>().is_ok() || slack::mention_id(word).is_some()
+        || word.contains(|c| c == '*' || c == '?')
+            && word.chars().all(|c| c.is_digit(16) || ":.*?".contains(c))
+}
+
+/// Parses an address, a network like `10.0.0.0/24` or a range like
+/// `10.0.0.10-50`. Returns `None` for values which don't start with an
+/// address.
+fn addresses(value: &str) -> Option<Result<IpRange, String>> {
+    if let Ok(ip) = value.parse::<IpAddr>() {
+        return Some(Ok(IpRange { start: ip, end: ip }));
+    }
+    let split = value.find(|c| c == '/' || c == '-' || c == '–')?;
+    value[..split].parse::<IpAddr>().ok()?;
+    Some(if value[split..].starts_with('/') {
+        value.parse::<Cidr>().map(|c| c.range())
+    } else {
+        value.parse::<IpRange>()
+    })
+}
+
+fn term(
+    text: &str,
+    quoted: bool,
+    qualifier: Option<&str>,
+    position: usize,
+    custom: &[String],
+) -> Result<Term, ParseError> {
+    let (field, value) = match qualifier {
+        Some(name) => match Field::from_name(name, custom) {
+            Some(field) => (field, text),
+            None => return error(format!("Unknown field '{}'", name), position),
+        },
+        None => match text.find(':') {
+            Some(i) if !quoted && !is_plain_value(text) => {
+                let name = &text[..i];
+                match Field::from_name(name, custom) {
+                    Some(field) => (field, &text[i + 1..]),
+                    None => return error(format!("Unknown field '{}'", name), position),
+                }
+            }
+            _ => (Field::Any, text),
+        },
+    };
+    if value.is_empty() {
+        return error(format!("Missing value after '{}'", text), position);
+    }
+    let wildcard = !quoted && value.contains(|c| c == '*' || c == '?');
+    if wildcard && field.is_exact() {
+        return error(format!("Wildcards can't be used in '{}'", text), position);
+    }
+    match field {
+        Field::Port if value.parse::<PortSpec>().is_err() => {
+            return error(format!("Invalid port '{}'", value), position)
+        }
+        Field::Mac if value.parse::<MacAddr>().is_err() => {
+            return error(format!("Invalid MAC address '{}'", value), position)
+        }
+        Field::Status if Status::from_keyword(value).is_none() => {
+            return error(
+                format!(
+                    "Unknown status '{}', expected one of {}",
+                    value,
+                    status::ALL
+                        .iter()
+                        .map(|s| s.as_str())
+                        .collect::<Vec<&str>>()
+                        .join(", ")
+                ),
+                position,
+            )
+        }
+        _ => {}
+    }
+    let range = match field {
+        Field::Ip | Field::Any if !quoted && !wildcard => match addresses(value) {
+            Some(Ok(range)) => Some(range),
+            Some(Err(e)) => return error(e, position),
+            None => None,
+        },
+        _ => None,
+    };
+    Ok(Term {
+        field,
+        value: value.to_owned(),
+        wildcard,
+        range,
+    })
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    /// Where the end of the query is reported.
+    end: usize,
+    custom: &'a [String],
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|t| &t.0)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.next).map_or(self.end, |t| t.1)
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next += 1;
+            let right = self.and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut left = self.not()?;
+        loop {
+            match self.peek() {
+                Some(&Token::And) => self.next += 1,
+                Some(&Token::Open) | Some(&Token::Not) | Some(&Token::Word { .. }) => {}
+                _ => return Ok(left),
+            }
+            let right = self.not()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+    }
+
+    fn not(&mut self) -> Result<Expr, ParseError> {
+        if self.peek() == Some(&Token::Not) {
+            self.next += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, ParseError> {
+        let position = self.position();
+        let token = match self.tokens.get(self.next) {
+            Some(t) => t.0.clone(),
+            None => return error("Expected a search term at the end".to_owned(), position),
+        };
+        self.next += 1;
+        match token {
+            Token::Open => {
+                let expr = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return error("Unclosed parenthesis".to_owned(), position);
+                }
+                self.next += 1;
+                Ok(expr)
+            }
+            Token::Word {
+                text,
+                quoted,
+                field,
+            } => Ok(Expr::Term(term(
+                &text,
+                quoted,
+                field.as_ref().map(|f| f.as_str()),
+                position,
+                self.custom,
+            )?)),
+            Token::Close => error("Unexpected ')'".to_owned(), position),
+            Token::And | Token::Or => error(
+                "Expected a search term before AND or OR".to_owned(),
+                position,
+            ),
+            Token::Not => unreachable!(),
+        }
+    }
+}
+
+/// Parses a query, leaving out its sort order. `custom` are the names of the
+/// custom fields, which can be used as qualifiers too.
+pub fn parse(s: &str, custom: &[String]) -> Result<Expr, ParseError> {
+    parse_sorted(s, custom).map(|(expr, _)| expr)
+}
+
+/// Parses a query along with the order given by a `sort:` term in it, which
+/// is by IP if there's none.
+pub fn parse_sorted(s: &str, custom: &[String]) -> Result<(Expr, Order), ParseError> {
+    let mut tokens = vec![];
+    let mut order = None;
+    for (token, position) in tokenize(s)? {
+        let key = match token {
+            Token::Word {
+                ref text,
+                quoted: false,
+                ..
+            } if text.starts_with("sort:") => &text[5..],
+            Token::Word {
+                ref text,
+                field: Some(ref field),
+                ..
+            } if field == "sort" => text,
+            _ => {
+                tokens.push((token, position));
+                continue;
+            }
+        };
+        if order.is_some() {
+            return error("Only one sort order can be given".to_owned(), position);
+        }
+        match key.parse::<Order>() {
+            Ok(o) => order = Some(o),
+            Err(e) => return error(e, position),
+        }
+    }
+    let order = order.unwrap_or_default();
+
+    let mut parser = Parser {
+        tokens,
+        next: 0,
+        end: s.chars().count(),
+        custom,
+    };
+    if parser.tokens.is_empty() {
+        return Ok((Expr::All, order));
+    }
+    let expr = parser.or()?;
+    if parser.peek().is_some() {
+        return error("Unexpected ')'".to_owned(), parser.position());
+    }
+    Ok((expr, order))
+}
+
+impl Expr {
+    /// Returns `None` when `entry` doesn't match, or else the value of the
+    /// first matching term to show with it, if there is one.
+    pub fn eval(&self, entry: &Entry) -> Option<Option<String>> {
+        match *self {
+            Expr::All => Some(None),
+            Expr::Term(ref term) => term.eval(entry),
+            Expr::And(ref left, ref right) => {
+                let left = left.eval(entry)?;
+                let right = right.eval(entry)?;
+                Some(left.or(right))
+            }
+            Expr::Or(ref left, ref right) => left.eval(entry).or_else(|| right.eval(entry)),
+            Expr::Not(ref expr) => match expr.eval(entry) {
+                Some(_) => None,
+                None => Some(None),
+            },
+        }
+    }
+}
+
+impl Term {
+    /// Matches a text value: by wildcard against the whole of it, or else
+    /// against any part of it.
+    pub fn matches_text(&self, text: &str) -> bool {
+        if self.wildcard {
+            glob(&self.value, text)
+        } else {
+            text.contains(self.value.as_str())
+        }
+    }
+
+    fn eval(&self, entry: &Entry) -> Option<Option<String>> {
+        let fields: &[Field] = match self.field {
+            Field::Any => &[
+                Field::Ip,
+                Field::Domain,
+                Field::Status,
+                Field::Port,
+                Field::Mac,
+                Field::Owner,
+                Field::Desc,
+            ],
+            ref field => ::std::slice::from_ref(field),
+        };
+        for field in fields {
+            if let Some(element) = self.eval_field(field, entry) {
+                return Some(element);
+            }
+        }
+        if self.field == Field::Any {
+            if let Some(value) = entry.custom.values().find(|v| self.matches_text(v)) {
+                return Some(Some(value.clone()));
+            }
+        }
+        None
+    }
+
+    fn eval_field(&self, field: &Field, entry: &Entry) -> Option<Option<String>> {
+        let q = self.value.as_str();
+        match *field {
+            Field::Any => None,
+            Field::Ip => {
+                let matched = match self.range {
+                    Some(ref range) => range.contains(&entry.ip),
+                    None => {
+                        !self.wildcard
+                            && ip::ipv6_prefix(q).map_or(false, |p| ip::has_prefix(&entry.ip, &p))
+                            || self.matches_text(&entry.ip.to_string())
+                    }
+                };
+                if matched {
+                    Some(None)
+                } else {
+                    None
+                }
+            }
+            Field::Domain => if entry.dns_names.iter().any(|n| self.matches_text(&n.name)) {
+                Some(Some(
+                    entry
+                        .dns_names
+                        .iter()
+                        .map(|n| n.name.as_str())
+                        .collect::<Vec<&str>>()
+                        .join(", "),
+                ))
+            } else {
+                None
+            },
+            Field::Status => if Status::from_keyword(q) == Some(entry.status) {
+                Some(Some(entry.status.label().to_owned()))
+            } else {
+                None
+            },
+            Field::Port => match q.parse::<PortSpec>() {
+                Ok(ref spec) if entry.open_ports.iter().any(|p| p.overlaps(spec)) => {
+                    Some(Some(entry.ports_as_string()))
+                }
+                _ => None,
+            },
+            Field::Mac => match q.parse::<MacAddr>() {
+                Ok(ref mac) if entry.has_mac(mac) => Some(Some(entry.macs_as_string())),
+                _ => None,
+            },
+            Field::Owner => {
+                // Unqualified terms only name an owner by mentioning them.
+                let id = match slack::mention_id(q) {
+                    Some(id) => id,
+                    None if self.field == Field::Owner => q,
+                    None => return None,
+                };
+                if entry.owner.as_ref().map_or(false, |o| o == id) {
+                    Some(Some(format!("<@{}>", id)))
+                } else {
+                    None
+                }
+            }
+            Field::Desc => match entry.description {
+                Some(ref d) if self.matches_text(d) => Some(Some(d.clone())),
+                _ => None,
+            },
+            Field::Tag => {
+                // Without wildcards, tags are matched exactly, never by part.
+                let matched = entry.tags.iter().any(|t| if self.wildcard {
+                    glob(q, t)
+                } else {
+                    t == q
+                });
+                if matched {
+                    Some(Some(entry.tags.join(", ")))
+                } else {
+                    None
+                }
+            }
+            Field::Custom(ref name) => match entry.custom.get(name) {
+                Some(v) if self.matches_text(v) => Some(Some(v.clone())),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Matches `text` as a whole against `pattern`, where `*` stands for any
+/// characters and `?` for a single one.
+pub fn glob(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the last `*` when the rest fails to match.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((sp, st)) = star {
+            p = sp + 1;
+            t = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::sort::SortKey;
+
+    fn word(field: Field, value: &str) -> Expr {
+        Expr::Term(Term {
+            field,
+            value: value.to_owned(),
+            wildcard: value.contains(|c| c == '*' || c == '?'),
+            range: None,
+        })
+    }
+
+    fn and(left: Expr, right: Expr) -> Expr {
+        Expr::And(Box::new(left), Box::new(right))
+    }
+
+    fn or(left: Expr, right: Expr) -> Expr {
+        Expr::Or(Box::new(left), Box::new(right))
+    }
+
+    fn not(expr: Expr) -> Expr {
+        Expr::Not(Box::new(expr))
+    }
+
+    fn parse_err(s: &str) -> ParseError {
+        parse(s, &[]).unwrap_err()
+    }
+
+    #[test]
+    fn empty_query() {
+        assert_eq!(parse("", &[]), Ok(Expr::All));
+        assert_eq!(parse("   ", &[]), Ok(Expr::All));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parse("web OR db mail", &[]),
+            Ok(or(
+                word(Field::Any, "web"),
+                and(word(Field::Any, "db"), word(Field::Any, "mail"))
+            ))
+        );
+        assert_eq!(
+            parse("web AND db OR mail", &[]),
+            Ok(or(
+                and(word(Field::Any, "web"), word(Field::Any, "db")),
+                word(Field::Any, "mail")
+            ))
+        );
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(
+            parse("(web OR db) mail", &[]),
+            Ok(and(
+                or(word(Field::Any, "web"), word(Field::Any, "db")),
+                word(Field::Any, "mail")
+            ))
+        );
+    }
+
+    #[test]
+    fn negation() {
+        assert_eq!(
+            parse("-tag:old web", &[]),
+            Ok(and(not(word(Field::Tag, "old")), word(Field::Any, "web")))
+        );
+        assert_eq!(
+            parse("NOT (web OR db)", &[]),
+            Ok(not(or(word(Field::Any, "web"), word(Field::Any, "db"))))
+        );
+        // A lone dash is a word, not a negation.
+        assert_eq!(parse("-", &[]), Ok(word(Field::Any, "-")));
+    }
+
+    #[test]
+    fn quoted_phrases() {
+        assert_eq!(
+            parse("\"web server\"", &[]),
+            Ok(word(Field::Any, "web server"))
+        );
+        assert_eq!(
+            parse("desc:\"web server\"", &[]),
+            Ok(word(Field::Desc, "web server"))
+        );
+        // Quotes keep wildcards and colons as they are.
+        assert_eq!(
+            parse("\"a*b:c\"", &[]),
+            Ok(Expr::Term(Term {
+                field: Field::Any,
+                value: "a*b:c".to_owned(),
+                wildcard: false,
+                range: None,
+            }))
+        );
+    }
+
+    #[test]
+    fn qualifiers() {
+        assert_eq!(parse("port:22", &[]), Ok(word(Field::Port, "22")));
+        assert_eq!(parse("dns:web", &[]), Ok(word(Field::Domain, "web")));
+        assert_eq!(parse("status:in-use", &[]), Ok(word(Field::Status, "in-use")));
+        assert_eq!(
+            parse("rack:B12", &["rack".to_owned()]),
+            Ok(word(Field::Custom("rack".to_owned()), "B12"))
+        );
+        assert_eq!(parse_err("rack:B12").message, "Unknown field 'rack'");
+    }
+
+    #[test]
+    fn plain_values_with_colons() {
+        assert_eq!(
+            parse("aa:bb:cc:dd:ee:ff", &[]),
+            Ok(word(Field::Any, "aa:bb:cc:dd:ee:ff"))
+        );
+        match parse("fe80::1", &[]) {
+            Ok(Expr::Term(ref t)) => {
+                assert_eq!(t.field, Field::Any);
+                assert!(t.range.is_some());
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn wildcards() {
+        assert_eq!(parse("web-*", &[]), Ok(word(Field::Any, "web-*")));
+        assert_eq!(parse("tag:db?", &[]), Ok(word(Field::Tag, "db?")));
+        assert_eq!(
+            parse_err("port:2*").message,
+            "Wildcards can't be used in 'port:2*'"
+        );
+    }
+
+    #[test]
+    fn glob_matches_whole_values() {
+        assert!(glob("web-*", "web-01"));
+        assert!(glob("web-??", "web-01"));
+        assert!(glob("*-01", "web-01"));
+        assert!(glob("w*b*1", "web-01"));
+        assert!(glob("*", ""));
+        assert!(!glob("web-?", "web-01"));
+        assert!(!glob("web", "web-01"));
+        assert!(!glob("*-02", "web-01"));
+    }
+
+    #[test]
+    fn address_ranges() {
+        let range = |s: &str| match parse(s, &[]) {
+            Ok(Expr::Term(t)) => t.range.map(|r| (r.start.to_string(), r.end.to_string())),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(
+            range("10.0.0.0/30"),
+            Some(("10.0.0.0".to_owned(), "10.0.0.3".to_owned()))
+        );
+        assert_eq!(
+            range("ip:10.0.0.10-50"),
+            Some(("10.0.0.10".to_owned(), "10.0.0.50".to_owned()))
+        );
+        assert_eq!(range("desc:10.0.0.1"), None);
+        assert!(parse("10.0.0.50-10", &[]).is_err());
+    }
+
+    #[test]
+    fn error_positions() {
+        assert_eq!(
+            parse_err("web (db"),
+            ParseError {
+                message: "Unclosed parenthesis".to_owned(),
+                position: 4,
+            }
+        );
+        assert_eq!(parse_err("web db)").position, 6);
+        assert_eq!(parse_err("web \"db").message, "Unclosed quote");
+        assert_eq!(parse_err("web \"db").position, 4);
+        assert_eq!(parse_err("OR web").position, 0);
+        assert_eq!(parse_err("web OR").position, 6);
+        assert_eq!(parse_err("web port:").message, "Missing value after 'port:'");
+        assert_eq!(parse_err("web port:").position, 4);
+        assert_eq!(parse_err("web port:http").message, "Invalid port 'http'");
+        assert_eq!(parse_err("ä port:http").position, 2);
+        assert_eq!(
+            format!("{}", parse_err("web (db")),
+            "Unclosed parenthesis (at character 5)"
+        );
+    }
+
+    #[test]
+    fn sort_orders() {
+        let (expr, order) = parse_sorted("web sort:-modified", &[]).unwrap();
+        assert_eq!(expr, word(Field::Any, "web"));
+        assert_eq!(
+            order,
+            Order {
+                key: SortKey::Modified,
+                descending: true,
+            }
+        );
+        assert_eq!(parse_sorted("", &[]).unwrap().1, Order::default());
+        assert_eq!(
+            parse_sorted("sort:ip sort:domain", &[]).unwrap_err().message,
+            "Only one sort order can be given"
+        );
+        assert!(parse_sorted("sort:size", &[]).is_err());
+    }
+}
@@ -0,0 +1,389 @@
+//! Versioning of the stored `Entry` format.
+//!
+//! Every stored entry carries a `schema_version`. Records written by an older
+//! version are upgraded step by step through `MIGRATIONS` when read, so a
+//! field added to or removed from `Entry` only needs one more migration here.
+
+extern crate toml;
+
+use std::net::IpAddr;
+
+use self::toml::Value;
+use self::toml::value::Table;
+
+use super::dns::{DnsName, RecordType};
+use super::error::{ErrorKind, Result};
+use super::status::Status;
+
+/// The version of the records written by this build.
+pub const CURRENT_VERSION: u32 = 7;
+
+type Migration = fn(&mut Table) -> Result<()>;
+
+/// `MIGRATIONS[n]` upgrades a record of version `n` to version `n + 1`.
+const MIGRATIONS: &[Migration] = &[
+    v0_unversioned,
+    v1_revision,
+    v2_canonical_ip,
+    v3_port_specs,
+    v4_dns_names,
+    v5_status,
+    v6_custom_table,
+];
+
+/// Records written before versioning have the same fields as version 1.
+fn v0_unversioned(_: &mut Table) -> Result<()> {
+    Ok(())
+}
+
+/// Version 2 adds the `revision` counter used to detect conflicting edits.
+fn v1_revision(record: &mut Table) -> Result<()> {
+    record
+        .entry("revision".to_owned())
+        .or_insert(Value::Integer(0));
+    Ok(())
+}
+
+/// Version 3 parses `ip` as an address, so it's rewritten in its canonical
+/// form. An unparsable `ip` is left alone and reported when deserializing.
+fn v2_canonical_ip(record: &mut Table) -> Result<()> {
+    let canonical = match record.get("ip") {
+        Some(&Value::String(ref ip)) => ::ip::normalize(ip),
+        _ => None,
+    };
+    if let Some(ip) = canonical {
+        record.insert("ip".to_owned(), Value::String(ip));
+    }
+    Ok(())
+}
+
+/// Version 4 writes open ports as specifications like `"53/udp"` instead of
+/// numbers. Port numbers out of range, which older versions accepted, are
+/// dropped and noted in the description.
+fn v3_port_specs(record: &mut Table) -> Result<()> {
+    let ports = match record.get("open_ports") {
+        Some(&Value::Array(ref ports)) => ports.clone(),
+        _ => return Ok(()),
+    };
+    let mut specs = vec![];
+    let mut dropped = vec![];
+    for port in ports {
+        match port {
+            Value::Integer(p) if p > 0 && p <= 65_535 => specs.push(Value::String(p.to_string())),
+            p => dropped.push(p.to_string()),
+        }
+    }
+    record.insert("open_ports".to_owned(), Value::Array(specs));
+    if !dropped.is_empty() {
+        note(
+            record,
+            &format!("이전 버전에서 옮기지 못한 열린 포트: {}", dropped.join(", ")),
+        );
+    }
+    Ok(())
+}
+
+/// Version 5 replaces the single `domain` with a list of `dns_names`. The
+/// domain becomes the primary address record of the entry. Older versions
+/// took any text as the domain, so one which isn't a valid DNS name is noted
+/// in the description instead.
+fn v4_dns_names(record: &mut Table) -> Result<()> {
+    let domain = match record.remove("domain") {
+        Some(Value::String(domain)) => domain,
+        Some(d) => {
+            note(record, &format!("이전 버전에서 옮기지 못한 도메인: {}", d));
+            return Ok(());
+        }
+        None => return Ok(()),
+    };
+    if domain.trim().is_empty() {
+        return Ok(());
+    }
+    let address = match record.get("ip") {
+        Some(&Value::String(ref ip)) => ip.parse::<IpAddr>().ok().map(|ip| RecordType::address(&ip)),
+        _ => None,
+    };
+    let mut name: DnsName = match domain.parse() {
+        Ok(name) => name,
+        Err(_) => {
+            note(record, &format!("이전 버전에서 옮기지 못한 도메인: {}", domain.trim()));
+            return Ok(());
+        }
+    };
+    name.record = address.unwrap_or(RecordType::A);
+    name.primary = true;
+    record.insert(
+        "dns_names".to_owned(),
+        Value::Array(vec![Value::String(name.to_string())]),
+    );
+    Ok(())
+}
+
+/// Version 6 replaces the `using` flag with the lifecycle `status`.
+fn v5_status(record: &mut Table) -> Result<()> {
+    let status = match record.remove("using") {
+        Some(Value::Boolean(true)) => Status::InUse,
+        Some(Value::Boolean(false)) => Status::Available,
+        Some(u) => bail!(ErrorKind::InvalidSchema(format!("Invalid using: {}", u))),
+        None => return Ok(()),
+    };
+    record.insert(
+        "status".to_owned(),
+        Value::String(status.as_str().to_owned()),
+    );
+    Ok(())
+}
+
+/// The keys of `Entry` itself as of version 7.
+const ENTRY_KEYS: &[&str] = &[
+    "schema_version",
+    "revision",
+    "modified",
+    "ip",
+    "dns_names",
+    "status",
+    "open_ports",
+    "description",
+    "owner",
+    "tags",
+    "lease_expires",
+    "macs",
+    "hostname",
+    "model",
+    "location",
+    "custom",
+];
+
+/// Version 7 moves the values of custom fields from the top level of the
+/// record into a `custom` table.
+fn v6_custom_table(record: &mut Table) -> Result<()> {
+    let keys: Vec<String> = record
+        .keys()
+        .filter(|k| !ENTRY_KEYS.contains(&k.as_str()))
+        .cloned()
+        .collect();
+    if keys.is_empty() {
+        return Ok(());
+    }
+    let mut custom = Table::new();
+    for key in keys {
+        let value = match record.remove(&key) {
+            Some(Value::String(v)) => v,
+            Some(v) => v.to_string(),
+            None => continue,
+        };
+        custom.insert(key, Value::String(value));
+    }
+    record.insert("custom".to_owned(), Value::Table(custom));
+    Ok(())
+}
+
+/// Appends a line to the description, keeping what a migration couldn't carry
+/// over where the entry's users will see it.
+fn note(record: &mut Table, line: &str) {
+    let description = match record.remove("description") {
+        Some(Value::String(ref d)) if d.trim().is_empty() => line.to_owned(),
+        Some(Value::String(d)) => format!("{}\n{}", d, line),
+        Some(d) => format!("{}\n{}", d, line),
+        None => line.to_owned(),
+    };
+    record.insert("description".to_owned(), Value::String(description));
+}
+
+pub fn version(record: &Table) -> Result<u32> {
+    match record.get("schema_version") {
+        None => Ok(0),
+        Some(&Value::Integer(v)) if v >= 0 => Ok(v as u32),
+        Some(v) => bail!(ErrorKind::InvalidSchema(format!(
+            "schema_version must be a number, not {}",
+            v
+        ))),
+    }
+}
+
+pub fn is_current(record: &Value) -> bool {
+    match *record {
+        Value::Table(ref t) => version(t).ok() == Some(CURRENT_VERSION),
+        _ => false,
+    }
+}
+
+/// Upgrades `record` to `CURRENT_VERSION`. Returns whether anything changed.
+pub fn upgrade(record: &mut Value) -> Result<bool> {
+    let record = match *record {
+        Value::Table(ref mut t) => t,
+        _ => bail!(ErrorKind::InvalidSchema("record must be a table".to_owned())),
+    };
+    let from = version(record)?;
+    if from > CURRENT_VERSION {
+        bail!(ErrorKind::UnsupportedSchema(from));
+    }
+    for (v, migration) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        migration(record)?;
+        record.insert(
+            "schema_version".to_owned(),
+            Value::Integer(v as i64 + 1),
+        );
+    }
+    Ok(from != CURRENT_VERSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upgraded(s: &str) -> Table {
+        let mut record: Value = s.parse().unwrap();
+        upgrade(&mut record).unwrap();
+        match record {
+            Value::Table(t) => t,
+            _ => unreachable!(),
+        }
+    }
+
+    fn string<'a>(record: &'a Table, key: &str) -> Option<&'a str> {
+        record.get(key).and_then(|v| v.as_str())
+    }
+
+    fn strings(record: &Table, key: &str) -> Vec<String> {
+        record
+            .get(key)
+            .and_then(|v| v.as_array())
+            .map(|a| a.iter().map(|v| v.as_str().unwrap().to_owned()).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn one_migration_per_version() {
+        assert_eq!(MIGRATIONS.len(), CURRENT_VERSION as usize);
+    }
+
+    #[test]
+    fn unversioned_record() {
+        let record = upgraded(
+            r#"
+            ip = " 10.0.0.1"
+            using = true
+            open_ports = [22, 80]
+            domain = "web.example.com"
+            description = "web server"
+            rack = "B12"
+            rack_units = 2
+            "#,
+        );
+        assert_eq!(record.get("schema_version"), Some(&Value::Integer(7)));
+        assert_eq!(record.get("revision"), Some(&Value::Integer(0)));
+        assert_eq!(string(&record, "ip"), Some("10.0.0.1"));
+        assert_eq!(string(&record, "status"), Some("in-use"));
+        assert!(record.get("using").is_none());
+        assert_eq!(strings(&record, "open_ports"), vec!["22", "80"]);
+        assert!(record.get("domain").is_none());
+        assert_eq!(
+            strings(&record, "dns_names"),
+            vec!["web.example.com A primary"]
+        );
+        assert_eq!(string(&record, "description"), Some("web server"));
+        let custom = record.get("custom").and_then(|c| c.as_table()).unwrap();
+        assert_eq!(custom.get("rack"), Some(&Value::String("B12".to_owned())));
+        assert_eq!(custom.get("rack_units"), Some(&Value::String("2".to_owned())));
+        assert!(record.get("rack").is_none());
+    }
+
+    #[test]
+    fn upgrades_from_the_stored_version() {
+        // Only the migrations after the stored version run, so `revision`
+        // isn't added.
+        let record = upgraded(
+            r#"
+            schema_version = 5
+            ip = "10.0.0.1"
+            using = false
+            open_ports = ["22"]
+            "#,
+        );
+        assert_eq!(string(&record, "status"), Some("available"));
+        assert!(record.get("revision").is_none());
+        assert_eq!(strings(&record, "open_ports"), vec!["22"]);
+    }
+
+    #[test]
+    fn current_record_is_left_alone() {
+        let mut record: Value = "schema_version = 7\nip = \"10.0.0.1\"".parse().unwrap();
+        assert!(is_current(&record));
+        assert_eq!(upgrade(&mut record).unwrap(), false);
+    }
+
+    #[test]
+    fn invalid_versions() {
+        for s in &["schema_version = 8", "schema_version = -1", "schema_version = \"7\""] {
+            let mut record: Value = s.parse().unwrap();
+            assert!(upgrade(&mut record).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn ports_out_of_range_are_noted() {
+        let record = upgraded("ip = \"10.0.0.1\"\nopen_ports = [22, 0, 70000]\n");
+        assert_eq!(strings(&record, "open_ports"), vec!["22"]);
+        assert_eq!(
+            string(&record, "description"),
+            Some("이전 버전에서 옮기지 못한 열린 포트: 0, 70000")
+        );
+    }
+
+    #[test]
+    fn invalid_domains_are_noted() {
+        let record = upgraded(
+            "ip = \"10.0.0.1\"\ndomain = \"web server!\"\ndescription = \"web\"\n",
+        );
+        assert!(strings(&record, "dns_names").is_empty());
+        assert_eq!(
+            string(&record, "description"),
+            Some("web\n이전 버전에서 옮기지 못한 도메인: web server!")
+        );
+
+        let record = upgraded("ip = \"10.0.0.1\"\ndomain = 42\n");
+        assert_eq!(
+            string(&record, "description"),
+            Some("이전 버전에서 옮기지 못한 도메인: 42")
+        );
+
+        let record = upgraded("ip = \"10.0.0.1\"\ndomain = \" \"\n");
+        assert!(strings(&record, "dns_names").is_empty());
+        assert!(record.get("description").is_none());
+    }
+
+    #[test]
+    fn ipv6_domain_is_an_aaaa_record() {
+        let record = upgraded("ip = \"2001:db8::1\"\ndomain = \"web.example.com\"\n");
+        assert_eq!(
+            strings(&record, "dns_names"),
+            vec!["web.example.com AAAA primary"]
+        );
+    }
+
+    #[test]
+    fn invalid_using() {
+        let mut record: Value = "ip = \"10.0.0.1\"\nusing = \"yes\"".parse().unwrap();
+        assert!(upgrade(&mut record).is_err());
+    }
+
+    #[test]
+    fn note_keeps_the_description() {
+        let mut record = Table::new();
+        note(&mut record, "a");
+        assert_eq!(string(&record, "description"), Some("a"));
+        note(&mut record, "b");
+        assert_eq!(string(&record, "description"), Some("a\nb"));
+
+        let mut record = Table::new();
+        record.insert("description".to_owned(), Value::String("  ".to_owned()));
+        note(&mut record, "a");
+        assert_eq!(string(&record, "description"), Some("a"));
+
+        let mut record = Table::new();
+        record.insert("description".to_owned(), Value::Integer(1));
+        note(&mut record, "a");
+        assert_eq!(string(&record, "description"), Some("1\na"));
+    }
+}
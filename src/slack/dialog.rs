@@ -17,6 +17,7 @@ pub struct Dialog {
     pub callback_id: String,
     pub title: String,
     pub elements: Vec<element::Element>,
+    pub state: Option<String>,
 }
 
 impl Dialog {
@@ -25,6 +26,7 @@ impl Dialog {
             callback_id,
             title,
             elements: vec![],
+            state: None,
         }
     }
 }
@@ -44,6 +46,7 @@ pub struct Submission {
     pub channel: super::Channel,
     pub action_ts: String,
     pub token: String,
+    pub state: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -52,6 +55,13 @@ pub struct SubmissionError {
     pub error: String,
 }
 
+/// Sent back instead of an empty response to keep the dialog open and show
+/// the errors next to the elements.
+#[derive(Serialize)]
+pub struct SubmissionErrors {
+    pub errors: Vec<SubmissionError>,
+}
+
 pub fn open(req: OpenRequest) -> super::super::error::Result<()> {
     let mut hm = ::std::collections::HashMap::new();
     hm.insert("token".to_owned(), req.token);
@@ -104,7 +114,9 @@ pub mod element {
         pub label: String,
         pub name: String,
         pub optional: Option<bool>,
-        pub options: Vec<SelectOption>,
+        #[serde(skip_serializing_if = "Vec::is_empty")] pub options: Vec<SelectOption>,
+        /// `"users"` to let Slack fill the options with the workspace members.
+        #[serde(skip_serializing_if = "Option::is_none")] pub data_source: Option<String>,
         pub value: Option<String>,
         pub placeholder: Option<String>,
     }
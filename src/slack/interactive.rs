@@ -0,0 +1,41 @@
+//! Buttons on messages. A click is posted to the same endpoint as dialog
+//! submissions, and the message responded with replaces the clicked one.
+
+#[derive(Serialize)]
+pub struct Action {
+    pub name: String,
+    pub text: String,
+    #[serde(rename = "type")] pub action_type: String,
+    /// Given back in the payload of the click.
+    pub value: String,
+}
+
+impl Action {
+    pub fn button(name: &str, text: &str, value: String) -> Action {
+        Action {
+            name: name.to_owned(),
+            text: text.to_owned(),
+            action_type: "button".to_owned(),
+            value,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Payload {
+    #[serde(rename = "type")] pub payload_type: String,
+    pub actions: Vec<ClickedAction>,
+    /// The `callback_id` of the attachment the button is on.
+    pub callback_id: String,
+    pub team: super::Team,
+    pub user: super::User,
+    pub channel: super::Channel,
+    pub action_ts: String,
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ClickedAction {
+    pub name: String,
+    pub value: String,
+}
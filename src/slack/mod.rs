@@ -5,6 +5,7 @@ extern crate serde_json;
 
 pub mod slash_command;
 pub mod dialog;
+pub mod interactive;
 
 use super::error::Result;
 
@@ -14,7 +15,7 @@ pub struct Channel {
     pub name: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct User {
     pub id: String,
     pub name: String,
@@ -35,6 +36,9 @@ pub struct AttachedMessage {
 pub struct Attachment {
     pub title: String,
     pub fields: Vec<AttachmentFields>,
+    /// Tells which attachment a button was clicked on.
+    #[serde(skip_serializing_if = "Option::is_none")] pub callback_id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")] pub actions: Vec<interactive::Action>,
 }
 
 #[derive(Serialize)]
@@ -43,6 +47,21 @@ pub struct AttachmentFields {
     pub value: String,
 }
 
+/// The user ID of a mention like `<@U024BE7LH>` or `<@U024BE7LH|alice>`, which
+/// is how slash commands receive `@alice` when escaping is turned on.
+pub fn mention_id(text: &str) -> Option<&str> {
+    if !text.starts_with("<@") || !text.ends_with('>') {
+        return None;
+    }
+    let inner = &text[2..text.len() - 1];
+    let id = inner.split('|').next().unwrap_or("");
+    if id.is_empty() {
+        None
+    } else {
+        Some(id)
+    }
+}
+
 fn encode_url(url: &str) -> String {
     url.to_owned()
         .replace(" ", "%20")
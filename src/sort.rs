@@ -0,0 +1,97 @@
+//! The order of search results, given in a query like `sort:domain` or
+//! `sort:-modified`.
+
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use super::ip::Entry;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SortKey {
+    /// Numerically, IPv4 addresses before IPv6 ones.
+    Ip,
+    /// The primary DNS name.
+    Domain,
+    /// In the lifecycle order of the statuses.
+    Status,
+    /// The Slack user ID of the owner.
+    Owner,
+    /// The time the entry was last saved.
+    Modified,
+}
+
+/// Entries without a value for the key come last either way, and entries with
+/// the same value are ordered by IP.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Order {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl Default for Order {
+    fn default() -> Order {
+        Order {
+            key: SortKey::Ip,
+            descending: false,
+        }
+    }
+}
+
+impl Order {
+    pub fn compare(&self, a: &Entry, b: &Entry) -> Ordering {
+        let ordering = match self.key {
+            SortKey::Ip => return self.directed(a.ip.cmp(&b.ip)),
+            SortKey::Domain => self.values(
+                a.primary_name().map(|n| &n.name),
+                b.primary_name().map(|n| &n.name),
+            ),
+            SortKey::Status => self.directed(a.status.cmp(&b.status)),
+            SortKey::Owner => self.values(a.owner.as_ref(), b.owner.as_ref()),
+            SortKey::Modified => self.values(a.modified, b.modified),
+        };
+        ordering.then_with(|| a.ip.cmp(&b.ip))
+    }
+
+    pub fn sort(&self, entries: &mut [Entry]) {
+        entries.sort_by(|a, b| self.compare(a, b));
+    }
+
+    fn directed(&self, ordering: Ordering) -> Ordering {
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+
+    fn values<T: Ord>(&self, a: Option<T>, b: Option<T>) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) => self.directed(a.cmp(&b)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+}
+
+impl FromStr for Order {
+    type Err = String;
+
+    /// Parses a key, reversed by a leading `-`.
+    fn from_str(s: &str) -> Result<Order, String> {
+        let (descending, key) = if s.starts_with('-') {
+            (true, &s[1..])
+        } else {
+            (false, s)
+        };
+        let key = match key {
+            "ip" => SortKey::Ip,
+            "domain" | "dns" | "name" => SortKey::Domain,
+            "status" => SortKey::Status,
+            "owner" => SortKey::Owner,
+            "modified" => SortKey::Modified,
+            _ => return Err(format!("Unknown sort key '{}'", key)),
+        };
+        Ok(Order { key, descending })
+    }
+}
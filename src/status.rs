@@ -0,0 +1,89 @@
+//! The lifecycle of an address.
+
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    /// Free to be issued.
+    Available,
+    /// Set aside for a planned use; not issued.
+    Reserved,
+    InUse,
+    /// Still in use, but to be given up.
+    Deprecated,
+    /// Must not be used at all, e.g. an address claimed by a rogue device.
+    Blocked,
+    /// No longer used, waiting for the address to be cleaned up before it
+    /// becomes available again.
+    ReclaimPending,
+}
+
+pub const ALL: &[Status] = &[
+    Status::Available,
+    Status::Reserved,
+    Status::InUse,
+    Status::Deprecated,
+    Status::Blocked,
+    Status::ReclaimPending,
+];
+
+impl Status {
+    /// The name stored in entries and used as the value of the dialog's select.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Status::Available => "available",
+            Status::Reserved => "reserved",
+            Status::InUse => "in-use",
+            Status::Deprecated => "deprecated",
+            Status::Blocked => "blocked",
+            Status::ReclaimPending => "reclaim-pending",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match *self {
+            Status::Available => "미사용",
+            Status::Reserved => "예약됨",
+            Status::InUse => "사용중",
+            Status::Deprecated => "폐기 예정",
+            Status::Blocked => "차단됨",
+            Status::ReclaimPending => "회수 대기",
+        }
+    }
+
+    /// The status a search term asks for: its label, with or without spaces,
+    /// or its stored name.
+    pub fn from_keyword(q: &str) -> Option<Status> {
+        ALL.iter().cloned().find(|s| {
+            q == s.as_str() || q == s.label().replace(' ', "")
+                || q == s.label()
+        })
+    }
+
+    /// Whether an entry may be changed from this status to `next`. Keeping
+    /// the status is always allowed.
+    pub fn can_become(&self, next: Status) -> bool {
+        use self::Status::*;
+        *self == next || match (*self, next) {
+            (Available, Reserved) | (Available, InUse) | (Available, Blocked) => true,
+            (Reserved, Available) | (Reserved, InUse) | (Reserved, Blocked) => true,
+            (InUse, Deprecated) | (InUse, ReclaimPending) | (InUse, Available) => true,
+            (Deprecated, InUse) | (Deprecated, ReclaimPending) | (Deprecated, Available) => true,
+            (ReclaimPending, Available) | (ReclaimPending, InUse) => true,
+            (Blocked, Available) => true,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Status {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Status, String> {
+        ALL.iter()
+            .cloned()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| format!("Invalid status: {}", s))
+    }
+}
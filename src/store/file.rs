@@ -0,0 +1,447 @@
+extern crate chrono;
+extern crate fs2;
+extern crate serde_json;
+extern crate toml;
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use self::chrono::{DateTime, Utc};
+
+use super::{first_revision, next_revision, FsckReport, Problem, Store, Trashed};
+use super::super::error::{ErrorKind, Result};
+use super::super::history::Event;
+use super::super::ip::{self, Entry};
+use super::super::schema;
+use super::super::slack::User;
+use super::super::sort::Order;
+
+pub struct FileStore {
+    data_path: String,
+}
+
+impl FileStore {
+    pub fn new(data_path: &str) -> FileStore {
+        FileStore {
+            data_path: data_path.to_owned(),
+        }
+    }
+
+    fn entry_path(&self, ip: &str) -> String {
+        format!("{}/{}", self.data_path, file_name(ip))
+    }
+
+    fn trash_dir(&self) -> String {
+        format!("{}/.trash", self.data_path)
+    }
+
+    fn trash_path(&self, ip: &str) -> String {
+        format!("{}/{}", self.trash_dir(), file_name(ip))
+    }
+
+    fn read_trashed(path: &Path) -> Option<Trashed> {
+        use std::io::Read;
+
+        let mut content = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut content))
+            .ok()?;
+        Trashed::from_toml(&content).ok()
+    }
+
+    fn quarantine_dir(&self) -> String {
+        format!("{}/.quarantine", self.data_path)
+    }
+
+    /// Reads every entry file of the data folder. Files that can't be read or
+    /// parsed are skipped and reported instead, paired with their file name.
+    fn scan(&self) -> (Vec<(String, Entry)>, Vec<Problem>) {
+        use std::fs::read_dir;
+        use std::io::Read;
+
+        let mut entries = vec![];
+        let mut problems = vec![];
+        let dir_entries = match read_dir(&self.data_path) {
+            Ok(d) => d,
+            Err(_) => return (entries, problems),
+        };
+        for e in dir_entries.filter_map(|e| e.ok()) {
+            let path = e.path();
+            if is_hidden(&path) || path.is_dir() {
+                continue;
+            }
+            let name = e.file_name().to_string_lossy().into_owned();
+            if path.extension().map(|x| x != "toml").unwrap_or(true) {
+                problems.push(Problem::Stray(name));
+                continue;
+            }
+            let mut content = String::new();
+            if let Err(err) = File::open(&path).and_then(|mut f| f.read_to_string(&mut content)) {
+                problems.push(Problem::Unreadable(name, err.to_string()));
+                continue;
+            }
+            match Entry::from_toml(&content) {
+                Ok(entry) => entries.push((name, entry)),
+                Err(err) => problems.push(Problem::Invalid(name, err.to_string())),
+            }
+        }
+        (entries, problems)
+    }
+
+    fn history_dir(&self) -> String {
+        format!("{}/.history", self.data_path)
+    }
+
+    fn history_path(&self, ip: &str) -> String {
+        format!("{}/{}.jsonl", self.history_dir(), file_key(ip))
+    }
+
+    /// Moves history files named after a non-canonical or unencoded address
+    /// to the name `history_path` expects. Expects the lock to be held.
+    fn migrate_history(&self) -> Result<()> {
+        use std::fs::{read_dir, rename};
+
+        let dir_entries = match read_dir(self.history_dir()) {
+            Ok(d) => d,
+            Err(_) => return Ok(()),
+        };
+        for e in dir_entries.filter_map(|e| e.ok()) {
+            let path = e.path();
+            let stem = match path.file_stem() {
+                Some(s) => s.to_string_lossy().replace('_', ":"),
+                None => continue,
+            };
+            let target = match ip::normalize(&stem) {
+                Some(ip) => PathBuf::from(self.history_path(&ip)),
+                None => continue,
+            };
+            if target != path && !target.exists() {
+                rename(&path, &target)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes an exclusive advisory lock on the data folder. The lock is held
+    /// until the returned file is dropped, so other server processes and
+    /// admin commands wait for the running modification to finish.
+    pub fn lock(&self) -> Result<File> {
+        use std::fs::OpenOptions;
+        use self::fs2::FileExt;
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(format!("{}/.lock", self.data_path))?;
+        file.lock_exclusive()?;
+        Ok(file)
+    }
+}
+
+/// Encodes `ip` for use in a file name. Colons aren't allowed in file names
+/// on every platform, so the ones of IPv6 addresses are written as `_`.
+pub fn file_key(ip: &str) -> String {
+    ip.replace(':', "_")
+}
+
+/// Name of the file holding `ip`, relative to the data folder.
+pub fn file_name(ip: &str) -> String {
+    format!("{}.toml", file_key(ip))
+}
+
+/// The IP stored in the entry file named `name`, if it is one.
+pub fn file_ip(name: &str) -> Option<String> {
+    if name.starts_with('.') || !name.ends_with(".toml") {
+        return None;
+    }
+    Some(name[..name.len() - ".toml".len()].replace('_', ":"))
+}
+
+/// Writes `content` to a hidden temporary file next to `path`, flushes it to
+/// disk and renames it over `path`, so readers see either the old or the new
+/// file but never a partially written one.
+fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    use std::fs::rename;
+    use std::io::Write;
+
+    let file_name = path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let tmp = path.with_file_name(format!(".{}.tmp", file_name));
+    {
+        let mut file: File = File::create(&tmp)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+    }
+    rename(&tmp, path)?;
+    sync_dir(path)
+}
+
+/// Flushes the directory entry changes of the folder containing `path`.
+fn sync_dir(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .map(|n| n.to_string_lossy().starts_with('.'))
+        .unwrap_or(false)
+}
+
+impl Store for FileStore {
+    fn add(&self, entry: &Entry) -> Result<()> {
+        let s = entry.to_toml()?;
+        let spath = self.entry_path(&entry.ip.to_string());
+        let p = Path::new(&spath);
+        let _lock = self.lock()?;
+        write_atomic(p, s.as_bytes())
+    }
+
+    fn create(&self, entry: &Entry) -> Result<Entry> {
+        let ip = entry.ip.to_string();
+        let spath = self.entry_path(&ip);
+        let p = Path::new(&spath);
+        let _lock = self.lock()?;
+        let saved = first_revision(self.get(&ip).as_ref(), entry)?;
+        write_atomic(p, saved.to_toml()?.as_bytes())?;
+        Ok(saved)
+    }
+
+    fn update(&self, entry: &Entry, revision: u64) -> Result<Entry> {
+        let ip = entry.ip.to_string();
+        let spath = self.entry_path(&ip);
+        let p = Path::new(&spath);
+        let _lock = self.lock()?;
+        let saved = next_revision(self.get(&ip).as_ref(), entry, revision)?;
+        write_atomic(p, saved.to_toml()?.as_bytes())?;
+        Ok(saved)
+    }
+
+    fn get(&self, ip: &str) -> Option<Entry> {
+        use std::io::Read;
+
+        let spath = self.entry_path(ip);
+        let p = Path::new(&spath);
+        let mut file: File = match File::open(&p) {
+            Ok(f) => f,
+            Err(_) => return None,
+        };
+        let mut content = String::new();
+        if file.read_to_string(&mut content).is_err() {
+            return None;
+        }
+        Entry::from_toml(&content).ok()
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        let mut entries: Vec<Entry> = self.scan().0.into_iter().map(|(_, e)| e).collect();
+        Order::default().sort(&mut entries);
+        entries
+    }
+
+    fn delete(&self, ip: &str, user: &User) -> Result<Entry> {
+        use std::fs::{create_dir_all, remove_file};
+
+        let spath = self.entry_path(ip);
+        let p = Path::new(&spath);
+        let _lock = self.lock()?;
+        let entry = match self.get(ip) {
+            Some(e) => e,
+            None => bail!(ErrorKind::EntryNotFound(ip.to_owned())),
+        };
+        let s = toml::to_string_pretty(&Trashed::new(entry.clone(), user))?;
+        create_dir_all(self.trash_dir())?;
+        write_atomic(Path::new(&self.trash_path(ip)), s.as_bytes())?;
+        remove_file(p)?;
+        sync_dir(p)?;
+        Ok(entry)
+    }
+
+    fn trash(&self) -> Vec<Trashed> {
+        use std::fs::read_dir;
+
+        let mut trashed: Vec<Trashed> = match read_dir(self.trash_dir()) {
+            Ok(d) => d.filter_map(|e| e.ok())
+                .filter(|e| !is_hidden(&e.path()))
+                .filter_map(|e| FileStore::read_trashed(&e.path()))
+                .collect(),
+            Err(_) => return vec![],
+        };
+        trashed.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+        trashed
+    }
+
+    fn restore(&self, ip: &str) -> Result<Option<Entry>> {
+        use std::fs::remove_file;
+
+        let spath = self.trash_path(ip);
+        let p = Path::new(&spath);
+        let _lock = self.lock()?;
+        let trashed = match FileStore::read_trashed(p) {
+            Some(t) => t,
+            None => return Ok(None),
+        };
+        let entry_path = self.entry_path(ip);
+        if Path::new(&entry_path).exists() {
+            bail!(ErrorKind::EntryExists(ip.to_owned()));
+        }
+        let s = trashed.entry.to_toml()?;
+        write_atomic(Path::new(&entry_path), s.as_bytes())?;
+        remove_file(p)?;
+        sync_dir(p)?;
+        Ok(Some(trashed.entry))
+    }
+
+    fn purge(&self, before: DateTime<Utc>) -> Result<usize> {
+        use std::fs::remove_file;
+
+        let _lock = self.lock()?;
+        let mut purged = 0;
+        for t in self.trash().into_iter().filter(|t| t.deleted_at < before) {
+            let spath = self.trash_path(&t.entry.ip.to_string());
+            remove_file(&spath)?;
+            purged += 1;
+        }
+        Ok(purged)
+    }
+
+    fn record(&self, event: &Event) -> Result<()> {
+        use std::fs::{create_dir_all, OpenOptions};
+        use std::io::Write;
+
+        let mut line = serde_json::to_string(event)?;
+        line.push('\n');
+        let _lock = self.lock()?;
+        create_dir_all(self.history_dir())?;
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(self.history_path(&event.ip))?;
+        file.write_all(line.as_bytes())?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    fn history(&self, ip: &str) -> Vec<Event> {
+        use std::io::{BufRead, BufReader};
+
+        let file = match File::open(self.history_path(ip)) {
+            Ok(f) => f,
+            Err(_) => return vec![],
+        };
+        BufReader::new(file)
+            .lines()
+            .filter_map(|l| l.ok())
+            .filter_map(|l| serde_json::from_str(&l).ok())
+            .collect()
+    }
+
+    fn migrate(&self) -> Result<usize> {
+        use std::fs::{read_dir, remove_file};
+        use std::io::Read;
+
+        let _lock = self.lock()?;
+        let mut migrated = 0;
+        for &(ref dir, trash) in &[(self.data_path.clone(), false), (self.trash_dir(), true)] {
+            let dir_entries = match read_dir(dir) {
+                Ok(d) => d,
+                Err(_) => continue,
+            };
+            for e in dir_entries.filter_map(|e| e.ok()) {
+                let path = e.path();
+                if is_hidden(&path) || path.extension().map(|x| x != "toml").unwrap_or(true) {
+                    continue;
+                }
+                let mut content = String::new();
+                File::open(&path)?.read_to_string(&mut content)?;
+                // Damaged files are left alone for `fsck` to report.
+                let value: toml::Value = match content.parse() {
+                    Ok(v) => v,
+                    Err(_) => continue,
+                };
+                let record = if trash { value.get("entry") } else { Some(&value) };
+                // Files written before addresses were normalized and encoded
+                // are moved to the name of their canonical address.
+                let target = match record
+                    .and_then(|r| r.get("ip"))
+                    .and_then(|ip| ip.as_str())
+                    .and_then(ip::normalize)
+                {
+                    Some(ip) => Path::new(dir).join(file_name(&ip)),
+                    None => path.clone(),
+                };
+                if target != path && target.exists() {
+                    // A duplicate; left alone for `fsck` to report.
+                    continue;
+                }
+                if record.map(schema::is_current).unwrap_or(true) && target == path {
+                    continue;
+                }
+                let upgraded = if trash {
+                    Trashed::from_toml(&content).and_then(|t| Ok(toml::to_string_pretty(&t)?))
+                } else {
+                    Entry::from_toml(&content).and_then(|e| e.to_toml())
+                };
+                if let Ok(upgraded) = upgraded {
+                    write_atomic(&target, upgraded.as_bytes())?;
+                    if target != path {
+                        remove_file(&path)?;
+                        sync_dir(&path)?;
+                    }
+                    migrated += 1;
+                }
+            }
+        }
+        self.migrate_history()?;
+        Ok(migrated)
+    }
+
+    fn fsck(&self, quarantine: bool) -> Result<FsckReport> {
+        use std::collections::BTreeMap;
+        use std::fs::{create_dir_all, rename};
+
+        let _lock = self.lock()?;
+        let (entries, mut problems) = self.scan();
+        let checked = entries.len() + problems.len();
+
+        let mut files_by_ip: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (name, entry) in entries {
+            let ip = entry.ip.to_string();
+            if name != file_name(&ip) {
+                problems.push(Problem::Mismatch(name.clone(), ip.clone()));
+            }
+            files_by_ip.entry(ip).or_insert_with(Vec::new).push(name);
+        }
+        for (ip, files) in files_by_ip {
+            if files.len() > 1 {
+                problems.push(Problem::Duplicate(ip, files));
+            }
+        }
+
+        let mut quarantined = vec![];
+        if quarantine {
+            let dir = self.quarantine_dir();
+            create_dir_all(&dir)?;
+            for name in problems.iter().filter_map(|p| p.quarantinable()) {
+                let mut target = format!("{}/{}", dir, name);
+                if Path::new(&target).exists() {
+                    target = format!("{}.{}", target, Utc::now().timestamp());
+                }
+                let source = format!("{}/{}", self.data_path, name);
+                rename(&source, &target)?;
+                sync_dir(Path::new(&source))?;
+                quarantined.push(name.to_owned());
+            }
+        }
+
+        Ok(FsckReport {
+            checked,
+            problems,
+            quarantined,
+        })
+    }
+}
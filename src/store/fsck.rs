@@ -0,0 +1,63 @@
+use std::fmt;
+
+/// Something wrong found while checking the stored entries.
+pub enum Problem {
+    /// A file that isn't an entry at all, like an editor backup file.
+    Stray(String),
+    Unreadable(String, String),
+    Invalid(String, String),
+    /// An entry stored under a name that doesn't match its `ip`.
+    Mismatch(String, String),
+    /// Several entries claiming the same `ip`.
+    Duplicate(String, Vec<String>),
+}
+
+impl Problem {
+    /// The stored name to move to the quarantine, if the problem is one that
+    /// makes the entry unusable.
+    pub fn quarantinable(&self) -> Option<&str> {
+        match *self {
+            Problem::Stray(ref f) | Problem::Unreadable(ref f, _) | Problem::Invalid(ref f, _) => {
+                Some(f)
+            }
+            Problem::Mismatch(..) | Problem::Duplicate(..) => None,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Problem::Stray(ref file) => write!(f, "{}: not an entry file", file),
+            Problem::Unreadable(ref file, ref e) => write!(f, "{}: unreadable, {}", file, e),
+            Problem::Invalid(ref file, ref e) => write!(f, "{}: parse error, {}", file, e),
+            Problem::Mismatch(ref file, ref ip) => write!(f, "{}: holds IP {}", file, ip),
+            Problem::Duplicate(ref ip, ref files) => {
+                write!(f, "{}: duplicated in {}", ip, files.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FsckReport {
+    pub checked: usize,
+    pub problems: Vec<Problem>,
+    pub quarantined: Vec<String>,
+}
+
+impl fmt::Display for FsckReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} checked, {} problems, {} quarantined",
+            self.checked,
+            self.problems.len(),
+            self.quarantined.len()
+        )?;
+        for p in &self.problems {
+            writeln!(f, "{}", p)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+extern crate chrono;
+
+use std::path::Path;
+use std::process::Command;
+
+use self::chrono::{DateTime, Utc};
+
+use super::{FileStore, FsckReport, Store, Trashed};
+use super::file::file_name;
+use super::super::error::{ErrorKind, Result};
+use super::super::history::{Change, Event};
+use super::super::ip::{Entry, Query};
+use super::super::mac::MacAddr;
+use super::super::port::PortSpec;
+use super::super::query::Expr;
+use super::super::schema;
+use super::super::slack::User;
+use super::super::sort::Order;
+
+/// A `FileStore` whose data folder is a git repository. Every recorded event
+/// becomes a commit of the entry file, authored as the Slack user.
+pub struct GitStore {
+    inner: FileStore,
+    data_path: String,
+}
+
+impl GitStore {
+    pub fn open(data_path: &str) -> Result<GitStore> {
+        let store = GitStore {
+            inner: FileStore::new(data_path),
+            data_path: data_path.to_owned(),
+        };
+        if !Path::new(data_path).join(".git").exists() {
+            let _lock = store.inner.lock()?;
+            store.git(&["init", "--quiet"])?;
+            store.git(&["add", "--all", "--", ":(glob)*.toml"])?;
+            if store.has_staged(None)? {
+                store.git(&["commit", "--quiet", "-m", "Import existing entries"])?;
+            }
+        }
+        Ok(store)
+    }
+
+    fn git(&self, args: &[&str]) -> Result<String> {
+        let output = Command::new("git")
+            .args(&[
+                "-c",
+                "user.name=ip_manager",
+                "-c",
+                "user.email=ip_manager@localhost",
+            ])
+            .args(args)
+            .current_dir(&self.data_path)
+            .output()?;
+        if !output.status.success() {
+            bail!(ErrorKind::Git(
+                String::from_utf8_lossy(&output.stderr).trim().to_owned()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn has_staged(&self, file: Option<&str>) -> Result<bool> {
+        let mut args = vec!["diff", "--cached", "--quiet"];
+        if let Some(file) = file {
+            args.push("--");
+            args.push(file);
+        }
+        let status = Command::new("git")
+            .args(&args)
+            .current_dir(&self.data_path)
+            .status()?;
+        Ok(!status.success())
+    }
+
+    fn commit(&self, event: &Event) -> Result<()> {
+        let file = file_name(&event.ip);
+        let _lock = self.inner.lock()?;
+        self.git(&["add", "--all", "--", &file])?;
+        if !self.has_staged(Some(&file))? {
+            return Ok(());
+        }
+        let author = format!("{} <{}@slack>", event.user.name, event.user.id);
+        self.git(&[
+            "commit",
+            "--quiet",
+            "--author",
+            &author,
+            "-m",
+            &commit_message(event),
+            "--",
+            &file,
+        ])?;
+        Ok(())
+    }
+}
+
+/// Builds messages like `edit 10.0.0.5: status available→in-use`, with every
+/// change spelled out in full in the body.
+fn commit_message(event: &Event) -> String {
+    fn summary(c: &Change) -> String {
+        let short = |v: &Option<String>| {
+            v.as_ref()
+                .map(|v| v.len() <= 32 && !v.contains('\n'))
+                .unwrap_or(true)
+        };
+        if short(&c.before) && short(&c.after) {
+            format!(
+                "{} {}→{}",
+                c.field,
+                c.before.as_ref().map(|s| s.as_str()).unwrap_or("-"),
+                c.after.as_ref().map(|s| s.as_str()).unwrap_or("-")
+            )
+        } else {
+            c.field.clone()
+        }
+    }
+
+    let mut message = format!("{} {}", event.action, event.ip);
+    if !event.changes.is_empty() {
+        message.push_str(": ");
+        message.push_str(&event
+            .changes
+            .iter()
+            .map(summary)
+            .collect::<Vec<String>>()
+            .join(", "));
+        message.push('\n');
+        for c in &event.changes {
+            message.push_str(&format!("\n{}", c));
+        }
+    }
+    message
+}
+
+impl Store for GitStore {
+    fn add(&self, entry: &Entry) -> Result<()> {
+        self.inner.add(entry)
+    }
+
+    fn create(&self, entry: &Entry) -> Result<Entry> {
+        self.inner.create(entry)
+    }
+
+    fn update(&self, entry: &Entry, revision: u64) -> Result<Entry> {
+        self.inner.update(entry, revision)
+    }
+
+    fn get(&self, ip: &str) -> Option<Entry> {
+        self.inner.get(ip)
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        self.inner.entries()
+    }
+
+    fn delete(&self, ip: &str, user: &User) -> Result<Entry> {
+        self.inner.delete(ip, user)
+    }
+
+    fn trash(&self) -> Vec<Trashed> {
+        self.inner.trash()
+    }
+
+    fn restore(&self, ip: &str) -> Result<Option<Entry>> {
+        self.inner.restore(ip)
+    }
+
+    fn purge(&self, before: DateTime<Utc>) -> Result<usize> {
+        self.inner.purge(before)
+    }
+
+    fn record(&self, event: &Event) -> Result<()> {
+        self.inner.record(event)?;
+        self.commit(event)
+    }
+
+    fn history(&self, ip: &str) -> Vec<Event> {
+        self.inner.history(ip)
+    }
+
+    fn list(&self, query: &Expr, order: &Order) -> Vec<Query> {
+        self.inner.list(query, order)
+    }
+
+    fn issue(&self, required_ports: &[PortSpec]) -> Option<Entry> {
+        self.inner.issue(required_ports)
+    }
+
+    fn with_mac(&self, mac: &MacAddr) -> Option<Entry> {
+        self.inner.with_mac(mac)
+    }
+
+    fn migrate(&self) -> Result<usize> {
+        let migrated = self.inner.migrate()?;
+        let _lock = self.inner.lock()?;
+        self.git(&["add", "--all", "--", ":(glob)*.toml"])?;
+        if self.has_staged(None)? {
+            self.git(&[
+                "commit",
+                "--quiet",
+                "-m",
+                &format!("Migrate entries to schema version {}", schema::CURRENT_VERSION),
+            ])?;
+        }
+        Ok(migrated)
+    }
+
+    fn fsck(&self, quarantine: bool) -> Result<FsckReport> {
+        self.inner.fsck(quarantine)
+    }
+
+    fn revision(&self, ip: &str, rev: Option<&str>) -> Result<Option<Entry>> {
+        let file = file_name(ip);
+        let rev = match rev {
+            Some(r) if r.starts_with('-') => bail!(ErrorKind::Git(format!("Invalid revision: {}", r))),
+            Some(r) => r.to_owned(),
+            // The version before the latest commit that touched the entry.
+            None => match self.git(&["log", "--format=%H", "-n", "2", "--", &file])?
+                .lines()
+                .nth(1)
+            {
+                Some(r) => r.to_owned(),
+                None => return Ok(None),
+            },
+        };
+        let content = match self.git(&["show", &format!("{}:{}", rev, file)]) {
+            Ok(c) => c,
+            Err(_) => return Ok(None),
+        };
+        Ok(Some(Entry::from_toml(&content)?))
+    }
+}
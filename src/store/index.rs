@@ -0,0 +1,268 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::collections::btree_map::Values;
+use std::net::IpAddr;
+
+use super::super::ip::{self, Entry, Query};
+use super::super::mac::MacAddr;
+use super::super::port::PortSpec;
+use super::super::query::{glob, Expr, Field, Term};
+use super::super::sort::Order;
+use super::super::status::Status;
+
+/// Entries kept in memory, keyed by IP, with secondary indexes on DNS name,
+/// open port, tag, MAC address and status.
+#[derive(Default)]
+pub struct Index {
+    /// Keyed by address, so entries are kept in numeric IP order.
+    entries: BTreeMap<IpAddr, Entry>,
+    by_name: HashMap<String, BTreeSet<IpAddr>>,
+    /// Single open ports. Entries with a port range are kept in `ranged`
+    /// instead of under every port of the range.
+    by_port: HashMap<u16, BTreeSet<IpAddr>>,
+    ranged: BTreeSet<IpAddr>,
+    by_tag: HashMap<String, BTreeSet<IpAddr>>,
+    by_mac: HashMap<MacAddr, BTreeSet<IpAddr>>,
+    by_status: HashMap<Status, BTreeSet<IpAddr>>,
+    /// Entries in use on a lease, which `issue` may reclaim once it expires.
+    leased: BTreeSet<IpAddr>,
+}
+
+impl Index {
+    pub fn new(entries: Vec<Entry>) -> Index {
+        let mut index = Index::default();
+        for entry in entries {
+            index.insert(entry);
+        }
+        index
+    }
+
+    pub fn insert(&mut self, entry: Entry) {
+        let ip = entry.ip;
+        self.remove(&ip.to_string());
+        for name in &entry.dns_names {
+            self.by_name
+                .entry(name.name.clone())
+                .or_insert_with(BTreeSet::new)
+                .insert(ip);
+        }
+        for port in &entry.open_ports {
+            if port.is_single() {
+                self.by_port
+                    .entry(port.start)
+                    .or_insert_with(BTreeSet::new)
+                    .insert(ip);
+            } else {
+                self.ranged.insert(ip);
+            }
+        }
+        for tag in &entry.tags {
+            self.by_tag
+                .entry(tag.clone())
+                .or_insert_with(BTreeSet::new)
+                .insert(ip);
+        }
+        for mac in &entry.macs {
+            self.by_mac
+                .entry(*mac)
+                .or_insert_with(BTreeSet::new)
+                .insert(ip);
+        }
+        self.by_status
+            .entry(entry.status)
+            .or_insert_with(BTreeSet::new)
+            .insert(ip);
+        if entry.status == Status::InUse && entry.lease_expires.is_some() {
+            self.leased.insert(ip);
+        }
+        self.entries.insert(ip, entry);
+    }
+
+    pub fn remove(&mut self, ip: &str) -> Option<Entry> {
+        fn unlink<K>(index: &mut HashMap<K, BTreeSet<IpAddr>>, key: &K, ip: &IpAddr)
+        where
+            K: ::std::hash::Hash + Eq,
+        {
+            let empty = match index.get_mut(key) {
+                Some(ips) => {
+                    ips.remove(ip);
+                    ips.is_empty()
+                }
+                None => false,
+            };
+            if empty {
+                index.remove(key);
+            }
+        }
+
+        let ip = &ip.parse::<IpAddr>().ok()?;
+        let entry = self.entries.remove(ip)?;
+        for name in &entry.dns_names {
+            unlink(&mut self.by_name, &name.name, ip);
+        }
+        for port in &entry.open_ports {
+            if port.is_single() {
+                unlink(&mut self.by_port, &port.start, ip);
+            }
+        }
+        self.ranged.remove(ip);
+        for tag in &entry.tags {
+            unlink(&mut self.by_tag, tag, ip);
+        }
+        for mac in &entry.macs {
+            unlink(&mut self.by_mac, mac, ip);
+        }
+        unlink(&mut self.by_status, &entry.status, ip);
+        self.leased.remove(ip);
+        Some(entry)
+    }
+
+    pub fn get(&self, ip: &str) -> Option<&Entry> {
+        self.entries.get(&ip.parse().ok()?)
+    }
+
+    pub fn contains(&self, ip: &str) -> bool {
+        self.get(ip).is_some()
+    }
+
+    /// Every entry, in IP order.
+    pub fn entries(&self) -> Values<IpAddr, Entry> {
+        self.entries.values()
+    }
+
+    pub fn with_mac(&self, mac: &MacAddr) -> Option<&Entry> {
+        self.lookup(self.by_mac.get(mac)).next()
+    }
+
+    fn lookup<'a>(
+        &'a self,
+        ips: Option<&'a BTreeSet<IpAddr>>,
+    ) -> Box<Iterator<Item = &'a Entry> + 'a> {
+        match ips {
+            Some(ips) => Box::new(ips.iter().filter_map(move |ip| self.entries.get(ip))),
+            None => Box::new(None.into_iter()),
+        }
+    }
+
+    /// Evaluates `query` only on the candidates its terms find in the
+    /// secondary indexes, or on every entry when they can't narrow it down.
+    pub fn list(&self, query: &Expr, order: &Order) -> Vec<Query> {
+        match self.candidates(query) {
+            Some(ips) => ip::list(query, order, self.lookup(Some(&ips))),
+            None => ip::list(query, order, self.entries()),
+        }
+    }
+
+    /// A superset of the entries matching `expr`, or `None` for all of them.
+    fn candidates(&self, expr: &Expr) -> Option<BTreeSet<IpAddr>> {
+        match *expr {
+            Expr::All | Expr::Not(_) => None,
+            Expr::Term(ref term) => self.term_candidates(term),
+            Expr::And(ref left, ref right) => match (self.candidates(left), self.candidates(right)) {
+                (Some(l), Some(r)) => Some(l.intersection(&r).cloned().collect()),
+                (l, r) => l.or(r),
+            },
+            Expr::Or(ref left, ref right) => {
+                let mut ips = self.candidates(left)?;
+                ips.extend(self.candidates(right)?);
+                Some(ips)
+            }
+        }
+    }
+
+    fn term_candidates(&self, term: &Term) -> Option<BTreeSet<IpAddr>> {
+        fn union<'a, I: Iterator<Item = &'a BTreeSet<IpAddr>>>(sets: I) -> BTreeSet<IpAddr> {
+            sets.flat_map(|s| s.iter().cloned()).collect()
+        }
+
+        let q = term.value.as_str();
+        Some(match term.field {
+            Field::Ip => match term.range {
+                Some(ref range) => self.entries
+                    .range(range.start..=range.end)
+                    .map(|(ip, _)| *ip)
+                    .collect(),
+                None => return None,
+            },
+            Field::Domain => union(
+                self.by_name
+                    .iter()
+                    .filter(|&(name, _)| term.matches_text(name))
+                    .map(|(_, ips)| ips),
+            ),
+            Field::Status => match Status::from_keyword(q) {
+                Some(status) => union(self.by_status.get(&status).into_iter()),
+                None => BTreeSet::new(),
+            },
+            Field::Port => match q.parse::<PortSpec>() {
+                Ok(spec) => {
+                    let mut ips = union(
+                        self.by_port
+                            .iter()
+                            .filter(|&(port, _)| spec.start <= *port && *port <= spec.end)
+                            .map(|(_, ips)| ips),
+                    );
+                    ips.extend(self.ranged.iter().cloned());
+                    ips
+                }
+                Err(_) => BTreeSet::new(),
+            },
+            Field::Tag => if term.wildcard {
+                union(
+                    self.by_tag
+                        .iter()
+                        .filter(|&(tag, _)| glob(q, tag))
+                        .map(|(_, ips)| ips),
+                )
+            } else {
+                union(self.by_tag.get(q).into_iter())
+            },
+            Field::Mac => match q.parse::<MacAddr>() {
+                Ok(mac) => union(self.by_mac.get(&mac).into_iter()),
+                Err(_) => BTreeSet::new(),
+            },
+            Field::Any | Field::Desc | Field::Owner | Field::Custom(_) => return None,
+        })
+    }
+
+    /// Intersects the available set with the candidates of every required
+    /// port, walking the smallest one. Expired leases are only reclaimed when
+    /// no available entry fits.
+    pub fn issue(&self, required_ports: &[PortSpec]) -> Option<Entry> {
+        let today = ip::today();
+        let expired: BTreeSet<IpAddr> = self.leased
+            .iter()
+            .filter(|ip| self.entries.get(*ip).map_or(false, |e| e.is_lease_expired(today)))
+            .cloned()
+            .collect();
+        self.find_issuable(self.by_status.get(&Status::Available), required_ports)
+            .or_else(|| self.find_issuable(Some(&expired), required_ports))
+    }
+
+    fn find_issuable(
+        &self,
+        available: Option<&BTreeSet<IpAddr>>,
+        required_ports: &[PortSpec],
+    ) -> Option<Entry> {
+        let empty = BTreeSet::new();
+        let candidates: Vec<BTreeSet<IpAddr>> = required_ports
+            .iter()
+            .map(|port| {
+                let mut ips = self.ranged.clone();
+                if port.is_single() {
+                    ips.extend(self.by_port.get(&port.start).into_iter().flat_map(|s| s.iter().cloned()));
+                }
+                ips
+            })
+            .collect();
+        let mut sets: Vec<&BTreeSet<IpAddr>> = vec![available.unwrap_or(&empty)];
+        sets.extend(candidates.iter());
+        sets.sort_by_key(|s| s.len());
+        let (smallest, rest) = sets.split_first()?;
+        smallest
+            .iter()
+            .filter(|ip| rest.iter().all(|s| s.contains(*ip)))
+            .filter_map(|ip| self.entries.get(ip))
+            .find(|e| ip::is_issuable(e, required_ports))
+            .cloned()
+    }
+}
@@ -0,0 +1,170 @@
+extern crate chrono;
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use self::chrono::{DateTime, Utc};
+
+use super::{first_revision, next_revision, Store, Trashed};
+use super::index::Index;
+use super::super::error::{ErrorKind, Result};
+use super::super::history::Event;
+use super::super::ip::{Entry, Query};
+use super::super::mac::MacAddr;
+use super::super::port::PortSpec;
+use super::super::query::Expr;
+use super::super::slack::User;
+use super::super::sort::Order;
+
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: RwLock<Index>,
+    history: RwLock<BTreeMap<String, Vec<Event>>>,
+    trash: RwLock<BTreeMap<String, Trashed>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl Store for MemoryStore {
+    fn add(&self, entry: &Entry) -> Result<()> {
+        self.entries
+            .write()
+            .map_err(|_| ErrorKind::Poisoned("memory store"))?
+            .insert(entry.clone());
+        Ok(())
+    }
+
+    fn create(&self, entry: &Entry) -> Result<Entry> {
+        let mut entries = self.entries
+            .write()
+            .map_err(|_| ErrorKind::Poisoned("memory store"))?;
+        let saved = first_revision(entries.get(&entry.ip.to_string()), entry)?;
+        entries.insert(saved.clone());
+        Ok(saved)
+    }
+
+    fn update(&self, entry: &Entry, revision: u64) -> Result<Entry> {
+        let mut entries = self.entries
+            .write()
+            .map_err(|_| ErrorKind::Poisoned("memory store"))?;
+        let saved = next_revision(entries.get(&entry.ip.to_string()), entry, revision)?;
+        entries.insert(saved.clone());
+        Ok(saved)
+    }
+
+    fn get(&self, ip: &str) -> Option<Entry> {
+        self.entries
+            .read()
+            .ok()
+            .and_then(|entries| entries.get(ip).cloned())
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        // The index keeps them in IP order already.
+        self.entries
+            .read()
+            .map(|entries| entries.entries().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn delete(&self, ip: &str, user: &User) -> Result<Entry> {
+        let entry = match self.entries
+            .write()
+            .map_err(|_| ErrorKind::Poisoned("memory store"))?
+            .remove(ip)
+        {
+            Some(e) => e,
+            None => bail!(ErrorKind::EntryNotFound(ip.to_owned())),
+        };
+        self.trash
+            .write()
+            .map_err(|_| ErrorKind::Poisoned("memory store"))?
+            .insert(ip.to_owned(), Trashed::new(entry.clone(), user));
+        Ok(entry)
+    }
+
+    fn trash(&self) -> Vec<Trashed> {
+        let mut trashed: Vec<Trashed> = self.trash
+            .read()
+            .map(|trash| trash.values().cloned().collect())
+            .unwrap_or_default();
+        trashed.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+        trashed
+    }
+
+    fn restore(&self, ip: &str) -> Result<Option<Entry>> {
+        let mut entries = self.entries
+            .write()
+            .map_err(|_| ErrorKind::Poisoned("memory store"))?;
+        let mut trash = self.trash
+            .write()
+            .map_err(|_| ErrorKind::Poisoned("memory store"))?;
+        if !trash.contains_key(ip) {
+            return Ok(None);
+        }
+        if entries.contains(ip) {
+            bail!(ErrorKind::EntryExists(ip.to_owned()));
+        }
+        Ok(trash.remove(ip).map(|t| {
+            entries.insert(t.entry.clone());
+            t.entry
+        }))
+    }
+
+    fn purge(&self, before: DateTime<Utc>) -> Result<usize> {
+        let mut trash = self.trash
+            .write()
+            .map_err(|_| ErrorKind::Poisoned("memory store"))?;
+        let count = trash.len();
+        let kept = trash
+            .iter()
+            .filter(|&(_, t)| t.deleted_at >= before)
+            .map(|(ip, t)| (ip.clone(), t.clone()))
+            .collect();
+        *trash = kept;
+        Ok(count - trash.len())
+    }
+
+    fn record(&self, event: &Event) -> Result<()> {
+        self.history
+            .write()
+            .map_err(|_| ErrorKind::Poisoned("memory store"))?
+            .entry(event.ip.clone())
+            .or_insert_with(Vec::new)
+            .push(event.clone());
+        Ok(())
+    }
+
+    fn history(&self, ip: &str) -> Vec<Event> {
+        self.history
+            .read()
+            .ok()
+            .and_then(|history| history.get(ip).cloned())
+            .unwrap_or_default()
+    }
+
+    fn list(&self, query: &Expr, order: &Order) -> Vec<Query> {
+        self.entries
+            .read()
+            .map(|entries| entries.list(query, order))
+            .unwrap_or_default()
+    }
+
+    fn issue(&self, required_ports: &[PortSpec]) -> Option<Entry> {
+        self.entries
+            .read()
+            .ok()
+            .and_then(|entries| entries.issue(required_ports))
+    }
+
+    fn with_mac(&self, mac: &MacAddr) -> Option<Entry> {
+        self.entries
+            .read()
+            .ok()
+            .and_then(|entries| entries.with_mac(mac).cloned())
+    }
+}
@@ -0,0 +1,185 @@
+pub mod file;
+pub mod fsck;
+pub mod git;
+pub mod index;
+pub mod memory;
+pub mod sqlite;
+pub mod watch;
+
+pub use self::file::FileStore;
+pub use self::fsck::{FsckReport, Problem};
+pub use self::git::GitStore;
+pub use self::memory::MemoryStore;
+pub use self::sqlite::SqliteStore;
+pub use self::watch::WatchedStore;
+
+extern crate chrono;
+extern crate toml;
+
+use self::chrono::{DateTime, Utc};
+
+use super::error::{ErrorKind, Result};
+use super::history::Event;
+use super::ip::{self, Entry, Query};
+use super::mac::MacAddr;
+use super::port::PortSpec;
+use super::query::Expr;
+use super::schema;
+use super::sort::Order;
+use super::settings::Settings;
+use super::slack::User;
+
+/// An entry moved to the trash by `Store::delete`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Trashed {
+    pub deleted_at: DateTime<Utc>,
+    pub deleted_by: User,
+    pub entry: Entry,
+}
+
+impl Trashed {
+    pub fn new(entry: Entry, user: &User) -> Trashed {
+        Trashed {
+            deleted_at: Utc::now(),
+            deleted_by: user.clone(),
+            entry,
+        }
+    }
+
+    /// Parses a trashed entry, upgrading the entry if it was written in an
+    /// older schema version.
+    pub fn from_toml(s: &str) -> Result<Trashed> {
+        let mut value: toml::Value = s.parse()?;
+        if let Some(entry) = value.get_mut("entry") {
+            schema::upgrade(entry)?;
+        }
+        Ok(value.try_into()?)
+    }
+}
+
+pub trait Store: Send + Sync {
+    fn add(&self, entry: &Entry) -> Result<()>;
+
+    fn get(&self, ip: &str) -> Option<Entry>;
+
+    /// Saves `entry` as a new entry at revision 0, failing with `EntryExists`
+    /// if its IP is stored already. Returns the saved entry.
+    fn create(&self, entry: &Entry) -> Result<Entry> {
+        let saved = first_revision(self.get(&entry.ip.to_string()).as_ref(), entry)?;
+        self.add(&saved)?;
+        Ok(saved)
+    }
+
+    /// Saves `entry` as the revision following `revision`, failing with
+    /// `StaleRevision` if the stored entry has been changed since, or with
+    /// `EntryNotFound` if it has been deleted. Returns the saved entry.
+    fn update(&self, entry: &Entry, revision: u64) -> Result<Entry> {
+        let saved = next_revision(self.get(&entry.ip.to_string()).as_ref(), entry, revision)?;
+        self.add(&saved)?;
+        Ok(saved)
+    }
+
+    /// Returns every entry, ordered by IP.
+    fn entries(&self) -> Vec<Entry>;
+
+    /// Moves `ip` to the trash. A previously trashed entry with the same IP
+    /// is replaced. Returns the entry as it was when it was trashed.
+    fn delete(&self, ip: &str, user: &User) -> Result<Entry>;
+
+    /// Returns the trashed entries, most recently deleted first.
+    fn trash(&self) -> Vec<Trashed>;
+
+    /// Moves `ip` back from the trash. Returns `None` when it isn't trashed.
+    fn restore(&self, ip: &str) -> Result<Option<Entry>>;
+
+    /// Removes the entries trashed before `before` for good.
+    fn purge(&self, before: DateTime<Utc>) -> Result<usize>;
+
+    fn record(&self, event: &Event) -> Result<()>;
+
+    /// Returns the recorded events of `ip`, oldest first.
+    fn history(&self, ip: &str) -> Vec<Event>;
+
+    /// Returns the entries matching `query` in `order`.
+    fn list(&self, query: &Expr, order: &Order) -> Vec<Query> {
+        ip::list(query, order, &self.entries())
+    }
+
+    fn issue(&self, required_ports: &[PortSpec]) -> Option<Entry> {
+        ip::issue(required_ports, &self.entries())
+    }
+
+    /// Returns the entry having `mac` among its MAC addresses.
+    fn with_mac(&self, mac: &MacAddr) -> Option<Entry> {
+        self.entries().into_iter().find(|e| e.has_mac(mac))
+    }
+
+    /// Rewrites every stored entry in the current schema version. Returns the
+    /// number of rewritten entries.
+    fn migrate(&self) -> Result<usize> {
+        let entries = self.entries();
+        for entry in &entries {
+            self.add(entry)?;
+        }
+        Ok(entries.len())
+    }
+
+    /// Checks the stored entries for damage. With `quarantine`, unusable
+    /// entries are moved out of the way so they stop showing up anywhere.
+    fn fsck(&self, _quarantine: bool) -> Result<FsckReport> {
+        Ok(FsckReport {
+            checked: self.entries().len(),
+            ..FsckReport::default()
+        })
+    }
+
+    /// Returns `ip` as it was at revision `rev`, or at the revision before
+    /// the latest change when `rev` is `None`.
+    fn revision(&self, _ip: &str, _rev: Option<&str>) -> Result<Option<Entry>> {
+        bail!(ErrorKind::NotSupported("revision"))
+    }
+}
+
+/// Checks that nothing is stored as `current` and returns `entry` at its
+/// first revision.
+pub fn first_revision(current: Option<&Entry>, entry: &Entry) -> Result<Entry> {
+    if current.is_some() {
+        bail!(ErrorKind::EntryExists(entry.ip.to_string()));
+    }
+    let mut saved = entry.clone();
+    saved.revision = 0;
+    saved.modified = Some(Utc::now());
+    Ok(saved)
+}
+
+/// Checks `revision` against the stored entry `current` and returns `entry`
+/// with its revision bumped.
+pub fn next_revision(current: Option<&Entry>, entry: &Entry, revision: u64) -> Result<Entry> {
+    let mut saved = entry.clone();
+    saved.revision = match current {
+        Some(c) if c.revision != revision => bail!(ErrorKind::StaleRevision(entry.ip.to_string())),
+        Some(c) => c.revision + 1,
+        None => bail!(ErrorKind::EntryNotFound(entry.ip.to_string())),
+    };
+    saved.modified = Some(Utc::now());
+    Ok(saved)
+}
+
+pub fn open(settings: &Settings) -> Result<Box<Store>> {
+    match settings.backend.as_ref().map(|b| b.as_str()) {
+        None | Some("file") => {
+            let store: Box<Store> = if settings.git.unwrap_or(false) {
+                Box::new(GitStore::open(&settings.data_path)?)
+            } else {
+                Box::new(FileStore::new(&settings.data_path))
+            };
+            if settings.index.unwrap_or(true) {
+                Ok(Box::new(WatchedStore::open(store, &settings.data_path)?))
+            } else {
+                Ok(store)
+            }
+        }
+        Some("sqlite") => Ok(Box::new(SqliteStore::open(&settings.sqlite_path())?)),
+        Some(b) => bail!(ErrorKind::InvalidBackend(b.to_owned())),
+    }
+}
@@ -0,0 +1,840 @@
+extern crate chrono;
+extern crate rusqlite;
+extern crate serde_json;
+extern crate toml;
+
+use std::net::IpAddr;
+use std::sync::{Mutex, MutexGuard};
+
+use self::chrono::{DateTime, Utc};
+use self::rusqlite::Connection;
+use self::rusqlite::types::ToSql;
+
+use super::{first_revision, next_revision, FsckReport, Problem, Store, Trashed};
+use super::super::dns::DnsName;
+use super::super::error::{ErrorKind, Result};
+use super::super::history::Event;
+use super::super::ip::{self, Entry, Query};
+use super::super::mac::MacAddr;
+use super::super::port::PortSpec;
+use super::super::query::{Expr, Field, Term};
+use super::super::slack::{self, User};
+use super::super::sort::Order;
+use super::super::status::Status;
+use super::super::subnet::IpRange;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS entries (
+        ip          TEXT PRIMARY KEY NOT NULL,
+        ip_key      BLOB, -- sorts in IP order, see `ip_key`
+        domain      TEXT, -- the primary DNS name
+        in_use      INTEGER NOT NULL, -- whether status is in-use
+        description TEXT,
+        owner       TEXT,
+        lease_expires TEXT,
+        status      TEXT,
+        data        TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS entries_ip_key ON entries (ip_key);
+    CREATE INDEX IF NOT EXISTS entries_domain ON entries (domain);
+    CREATE INDEX IF NOT EXISTS entries_status ON entries (status);
+    CREATE INDEX IF NOT EXISTS entries_owner ON entries (owner);
+    CREATE INDEX IF NOT EXISTS entries_lease_expires ON entries (lease_expires);
+    CREATE TABLE IF NOT EXISTS entry_port_ranges (
+        ip         TEXT NOT NULL,
+        protocol   TEXT NOT NULL,
+        first_port INTEGER NOT NULL,
+        last_port  INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS entry_port_ranges_ip ON entry_port_ranges (ip);
+    CREATE INDEX IF NOT EXISTS entry_port_ranges_port
+        ON entry_port_ranges (first_port, last_port);
+    CREATE TABLE IF NOT EXISTS entry_tags (
+        ip  TEXT NOT NULL,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (ip, tag)
+    );
+    CREATE INDEX IF NOT EXISTS entry_tags_tag ON entry_tags (tag);
+    CREATE TABLE IF NOT EXISTS entry_macs (
+        ip  TEXT NOT NULL,
+        mac TEXT NOT NULL,
+        PRIMARY KEY (ip, mac)
+    );
+    CREATE INDEX IF NOT EXISTS entry_macs_mac ON entry_macs (mac);
+    CREATE TABLE IF NOT EXISTS entry_dns_names (
+        ip          TEXT NOT NULL,
+        name        TEXT NOT NULL,
+        record_type TEXT NOT NULL,
+        is_primary  INTEGER NOT NULL,
+        ttl         INTEGER,
+        PRIMARY KEY (ip, name, record_type)
+    );
+    CREATE INDEX IF NOT EXISTS entry_dns_names_name ON entry_dns_names (name);
+    CREATE TABLE IF NOT EXISTS entry_custom (
+        ip    TEXT NOT NULL,
+        name  TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (ip, name)
+    );
+    CREATE TABLE IF NOT EXISTS history (
+        id   INTEGER PRIMARY KEY AUTOINCREMENT,
+        ip   TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS history_ip ON history (ip);
+    CREATE TABLE IF NOT EXISTS trash (
+        ip         TEXT PRIMARY KEY NOT NULL,
+        deleted_at INTEGER NOT NULL,
+        data       TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS trash_deleted_at ON trash (deleted_at);
+    CREATE TABLE IF NOT EXISTS quarantine (
+        id   INTEGER PRIMARY KEY AUTOINCREMENT,
+        ip   TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+";
+
+/// Matches the port rows of a protocol whose range starts at or before the
+/// first port parameter and ends at or after the second. Given the end and the
+/// start of a query, it finds overlapping ranges; given its start and end, it
+/// finds the ranges covering all of it.
+const PORT_MATCHES: &str = "(protocol = 'any' OR ? = 'any' OR protocol = ?) \
+                            AND first_port <= ? AND last_port >= ?";
+
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<SqliteStore> {
+        let mut conn = Connection::open(path)?;
+        add_missing_columns(&conn)?;
+        conn.execute_batch(SCHEMA)?;
+        upgrade_ports(&mut conn)?;
+        upgrade_dns_names(&mut conn)?;
+        upgrade_status(&conn)?;
+        upgrade_ip_keys(&mut conn)?;
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Copies every entry of `source` into this store in a single transaction.
+    pub fn import(&self, source: &Store) -> Result<usize> {
+        let entries = source.entries();
+        let mut conn = self.lock()?;
+        let tx = conn.transaction()?;
+        for entry in &entries {
+            insert(&tx, entry)?;
+        }
+        tx.commit()?;
+        Ok(entries.len())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<Connection>> {
+        self.conn
+            .lock()
+            .map_err(|_| ErrorKind::Poisoned("sqlite store").into())
+    }
+
+    fn select_data(&self, sql: &str, params: &[&ToSql]) -> Result<Vec<String>> {
+        let conn = self.lock()?;
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map(params, |row| row.get::<_, String>(0))?;
+        let mut data = vec![];
+        for d in rows {
+            data.push(d?);
+        }
+        Ok(data)
+    }
+
+    /// Runs a query selecting the `data` column of `entries`. Rows that fail
+    /// to parse are left out; `fsck` reports them.
+    fn select(&self, sql: &str, params: &[&ToSql]) -> Result<Vec<Entry>> {
+        Ok(self.select_data(sql, params)?
+            .iter()
+            .filter_map(|d| Entry::from_toml(d).ok())
+            .collect())
+    }
+
+    fn select_trash(&self, sql: &str, params: &[&ToSql]) -> Result<Vec<Trashed>> {
+        Ok(self.select_data(sql, params)?
+            .iter()
+            .filter_map(|d| Trashed::from_toml(d).ok())
+            .collect())
+    }
+}
+
+/// Reads `ip` within the transaction about to change it.
+fn current(conn: &Connection, ip: &str) -> Result<Option<Entry>> {
+    let mut stmt = conn.prepare("SELECT data FROM entries WHERE ip = ?1")?;
+    let mut rows = stmt.query_map(&[&ip], |row| row.get::<_, String>(0))?;
+    match rows.next() {
+        Some(data) => Ok(Some(Entry::from_toml(&data?)?)),
+        None => Ok(None),
+    }
+}
+
+/// The address as bytes which sort like `IpAddr` does: IPv4 addresses
+/// before IPv6 ones, each in numeric order. The `ip` column sorts as text,
+/// which puts 10.0.0.10 before 10.0.0.2.
+fn ip_key(ip: &IpAddr) -> Vec<u8> {
+    match *ip {
+        IpAddr::V4(ref v4) => {
+            let mut key = vec![4];
+            key.extend_from_slice(&v4.octets());
+            key
+        }
+        IpAddr::V6(ref v6) => {
+            let mut key = vec![6];
+            key.extend_from_slice(&v6.octets());
+            key
+        }
+    }
+}
+
+fn insert(conn: &Connection, entry: &Entry) -> Result<()> {
+    let ip = entry.ip.to_string();
+    conn.execute(
+        "INSERT OR REPLACE INTO entries
+         (ip, ip_key, domain, in_use, description, owner, lease_expires, status, data)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        &[
+            &ip,
+            &ip_key(&entry.ip),
+            &entry.primary_name().map(|n| n.name.clone()),
+            &(entry.status == Status::InUse),
+            &entry.description,
+            &entry.owner,
+            &entry.lease_expires.map(|d| d.to_string()),
+            &entry.status.as_str(),
+            &entry.to_toml()?,
+        ],
+    )?;
+    insert_ports(conn, &ip, &entry.open_ports)?;
+    insert_dns_names(conn, &ip, &entry.dns_names)?;
+    conn.execute("DELETE FROM entry_tags WHERE ip = ?1", &[&ip])?;
+    for tag in &entry.tags {
+        conn.execute(
+            "INSERT OR IGNORE INTO entry_tags (ip, tag) VALUES (?1, ?2)",
+            &[&ip, tag],
+        )?;
+    }
+    conn.execute("DELETE FROM entry_macs WHERE ip = ?1", &[&ip])?;
+    for mac in &entry.macs {
+        conn.execute(
+            "INSERT OR IGNORE INTO entry_macs (ip, mac) VALUES (?1, ?2)",
+            &[&ip, &mac.to_string()],
+        )?;
+    }
+    conn.execute("DELETE FROM entry_custom WHERE ip = ?1", &[&ip])?;
+    for (name, value) in &entry.custom {
+        conn.execute(
+            "INSERT INTO entry_custom (ip, name, value) VALUES (?1, ?2, ?3)",
+            &[&ip, name, value],
+        )?;
+    }
+    Ok(())
+}
+
+fn remove(conn: &Connection, ip: &str) -> Result<()> {
+    conn.execute("DELETE FROM entries WHERE ip = ?1", &[&ip])?;
+    conn.execute("DELETE FROM entry_port_ranges WHERE ip = ?1", &[&ip])?;
+    conn.execute("DELETE FROM entry_tags WHERE ip = ?1", &[&ip])?;
+    conn.execute("DELETE FROM entry_macs WHERE ip = ?1", &[&ip])?;
+    conn.execute("DELETE FROM entry_dns_names WHERE ip = ?1", &[&ip])?;
+    conn.execute("DELETE FROM entry_custom WHERE ip = ?1", &[&ip])?;
+    Ok(())
+}
+
+fn insert_ports(conn: &Connection, ip: &str, ports: &[PortSpec]) -> Result<()> {
+    conn.execute("DELETE FROM entry_port_ranges WHERE ip = ?1", &[&ip])?;
+    for port in ports {
+        conn.execute(
+            "INSERT INTO entry_port_ranges (ip, protocol, first_port, last_port)
+             VALUES (?1, ?2, ?3, ?4)",
+            &[
+                &ip,
+                &port.protocol.as_str(),
+                &i64::from(port.start),
+                &i64::from(port.end),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+fn insert_dns_names(conn: &Connection, ip: &str, names: &[DnsName]) -> Result<()> {
+    conn.execute("DELETE FROM entry_dns_names WHERE ip = ?1", &[&ip])?;
+    for name in names {
+        conn.execute(
+            "INSERT OR IGNORE INTO entry_dns_names (ip, name, record_type, is_primary, ttl)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            &[
+                &ip,
+                &name.name,
+                &name.record.as_str(),
+                &name.primary,
+                &name.ttl.map(i64::from),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Columns of `entries` added after it was first created. No entry stored
+/// before had a value for them, so the new columns are left empty.
+const ADDED_COLUMNS: &[(&str, &str)] = &[
+    ("owner", "TEXT"),
+    ("lease_expires", "TEXT"),
+    ("status", "TEXT"),
+    ("ip_key", "BLOB"),
+];
+
+fn add_missing_columns(conn: &Connection) -> Result<()> {
+    let tables: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'entries'",
+        &[],
+        |row| row.get(0),
+    )?;
+    if tables == 0 {
+        return Ok(());
+    }
+    let columns = {
+        let mut stmt = conn.prepare("PRAGMA table_info(entries)")?;
+        let rows = stmt.query_map(&[], |row| row.get::<_, String>(1))?;
+        let mut columns = vec![];
+        for c in rows {
+            columns.push(c?);
+        }
+        columns
+    };
+    for &(name, kind) in ADDED_COLUMNS {
+        if !columns.iter().any(|c| c == name) {
+            conn.execute_batch(&format!("ALTER TABLE entries ADD COLUMN {} {};", name, kind))?;
+        }
+    }
+    Ok(())
+}
+
+/// Databases created before port ranges kept single ports in `entry_ports`.
+/// Rebuilds the port table from the stored entries and drops the old one.
+fn upgrade_ports(conn: &mut Connection) -> Result<()> {
+    let legacy: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'entry_ports'",
+        &[],
+        |row| row.get(0),
+    )?;
+    if legacy == 0 {
+        return Ok(());
+    }
+    let tx = conn.transaction()?;
+    let rows = {
+        let mut stmt = tx.prepare("SELECT ip, data FROM entries")?;
+        let rows = stmt.query_map(&[], |row| {
+            (row.get::<_, String>(0), row.get::<_, String>(1))
+        })?;
+        let mut data = vec![];
+        for row in rows {
+            data.push(row?);
+        }
+        data
+    };
+    for (ip, data) in rows {
+        if let Ok(entry) = Entry::from_toml(&data) {
+            insert_ports(&tx, &ip, &entry.open_ports)?;
+        }
+    }
+    tx.execute_batch("DROP TABLE entry_ports;")?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Entries stored before DNS names were split out only have their domain in
+/// `entries`. Fills `entry_dns_names` for the ones which have a domain but no
+/// names yet.
+fn upgrade_dns_names(conn: &mut Connection) -> Result<()> {
+    let tx = conn.transaction()?;
+    let rows = {
+        let mut stmt = tx.prepare(
+            "SELECT ip, data FROM entries WHERE domain IS NOT NULL
+             AND ip NOT IN (SELECT ip FROM entry_dns_names)",
+        )?;
+        let rows = stmt.query_map(&[], |row| {
+            (row.get::<_, String>(0), row.get::<_, String>(1))
+        })?;
+        let mut data = vec![];
+        for row in rows {
+            data.push(row?);
+        }
+        data
+    };
+    for (ip, data) in rows {
+        if let Ok(entry) = Entry::from_toml(&data) {
+            insert_dns_names(&tx, &ip, &entry.dns_names)?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Entries stored before the lifecycle status only have `in_use`.
+fn upgrade_status(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "UPDATE entries SET status = CASE in_use WHEN 1 THEN 'in-use' ELSE 'available' END
+         WHERE status IS NULL",
+        &[],
+    )?;
+    Ok(())
+}
+
+/// Entries stored before `ip_key` was added have none. Fills it in from `ip`.
+fn upgrade_ip_keys(conn: &mut Connection) -> Result<()> {
+    let tx = conn.transaction()?;
+    let ips = {
+        let mut stmt = tx.prepare("SELECT ip FROM entries WHERE ip_key IS NULL")?;
+        let rows = stmt.query_map(&[], |row| row.get::<_, String>(0))?;
+        let mut ips = vec![];
+        for ip in rows {
+            ips.push(ip?);
+        }
+        ips
+    };
+    for ip in ips {
+        if let Ok(addr) = ip.parse::<IpAddr>() {
+            tx.execute(
+                "UPDATE entries SET ip_key = ?1 WHERE ip = ?2",
+                &[&ip_key(&addr), &ip],
+            )?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn like_pattern(q: &str) -> String {
+    format!("%{}%", escape_like(q))
+}
+
+/// Turns the wildcards of a query term into those of `LIKE`.
+fn glob_pattern(q: &str) -> String {
+    escape_like(q).replace('*', "%").replace('?', "_")
+}
+
+fn escape_like(q: &str) -> String {
+    q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Translates a query into a condition on `entries` which every matching
+/// entry meets. It may let through entries which don't match, since `LIKE`
+/// ignores case and negations aren't narrowed at all.
+fn condition(expr: &Expr, params: &mut Vec<Box<ToSql>>) -> String {
+    match *expr {
+        Expr::All | Expr::Not(_) => "1".to_owned(),
+        Expr::Term(ref term) => term_condition(term, params),
+        Expr::And(ref left, ref right) => format!(
+            "({}) AND ({})",
+            condition(left, params),
+            condition(right, params)
+        ),
+        Expr::Or(ref left, ref right) => format!(
+            "({}) OR ({})",
+            condition(left, params),
+            condition(right, params)
+        ),
+    }
+}
+
+/// Addresses are stored as text, so ranges are narrowed down to the IPv4
+/// addresses starting with the numbers shared by both ends, or else to the
+/// addresses of the family.
+fn range_condition(range: &IpRange) -> String {
+    if range.start == range.end {
+        "ip = ?".to_owned()
+    } else if range.start.is_ipv6() {
+        "ip LIKE '%:%'".to_owned()
+    } else {
+        "ip LIKE ? ESCAPE '\\'".to_owned()
+    }
+}
+
+/// The leading numbers shared by both ends of an IPv4 range, like `10.0.`
+/// for `10.0.1.0-10.0.2.255`.
+fn shared_octets(range: &IpRange) -> String {
+    let (start, end) = (range.start.to_string(), range.end.to_string());
+    let shared = start
+        .split('.')
+        .zip(end.split('.'))
+        .take_while(|&(a, b)| a == b)
+        .count();
+    start
+        .split('.')
+        .take(shared)
+        .map(|o| format!("{}.", o))
+        .collect()
+}
+
+fn term_condition(term: &Term, params: &mut Vec<Box<ToSql>>) -> String {
+    let q = term.value.as_str();
+    let pattern = if term.wildcard {
+        glob_pattern(q)
+    } else {
+        like_pattern(q)
+    };
+    let any = term.field == Field::Any;
+    let mut conditions: Vec<String> = vec![];
+    if any || term.field == Field::Ip {
+        match term.range {
+            Some(ref range) => {
+                conditions.push(range_condition(range));
+                if range.start == range.end {
+                    params.push(Box::new(range.start.to_string()));
+                } else if !range.start.is_ipv6() {
+                    params.push(Box::new(format!("{}%", escape_like(&shared_octets(range)))));
+                }
+            }
+            None => {
+                conditions.push("ip LIKE ? ESCAPE '\\'".to_owned());
+                params.push(Box::new(pattern.clone()));
+                if !term.wildcard && ip::ipv6_prefix(q).is_some() {
+                    conditions.push("ip LIKE '%:%'".to_owned());
+                }
+            }
+        }
+    }
+    if any || term.field == Field::Domain {
+        conditions.push(
+            "ip IN (SELECT ip FROM entry_dns_names WHERE name LIKE ? ESCAPE '\\')".to_owned(),
+        );
+        params.push(Box::new(pattern.clone()));
+    }
+    if any || term.field == Field::Desc {
+        conditions.push("description LIKE ? ESCAPE '\\'".to_owned());
+        params.push(Box::new(pattern.clone()));
+    }
+    if any {
+        conditions.push(
+            "ip IN (SELECT ip FROM entry_custom WHERE value LIKE ? ESCAPE '\\')".to_owned(),
+        );
+        params.push(Box::new(pattern.clone()));
+    }
+    if let Field::Custom(ref name) = term.field {
+        conditions.push(
+            "ip IN (SELECT ip FROM entry_custom WHERE name = ? AND value LIKE ? ESCAPE '\\')"
+                .to_owned(),
+        );
+        params.push(Box::new(name.clone()));
+        params.push(Box::new(pattern.clone()));
+    }
+    if any || term.field == Field::Status {
+        if let Some(status) = Status::from_keyword(q) {
+            conditions.push("status = ?".to_owned());
+            params.push(Box::new(status.as_str()));
+        }
+    }
+    if any || term.field == Field::Port {
+        if let Ok(port) = q.parse::<PortSpec>() {
+            let protocol = port.protocol.as_str();
+            conditions.push(format!("ip IN (SELECT ip FROM entry_port_ranges WHERE {})", PORT_MATCHES));
+            params.push(Box::new(protocol));
+            params.push(Box::new(protocol));
+            params.push(Box::new(i64::from(port.end)));
+            params.push(Box::new(i64::from(port.start)));
+        }
+    }
+    if any || term.field == Field::Mac {
+        if let Ok(mac) = q.parse::<MacAddr>() {
+            conditions.push("ip IN (SELECT ip FROM entry_macs WHERE mac = ?)".to_owned());
+            params.push(Box::new(mac.to_string()));
+        }
+    }
+    if any || term.field == Field::Owner {
+        let id = slack::mention_id(q).or(if any { None } else { Some(q) });
+        if let Some(id) = id {
+            conditions.push("owner = ?".to_owned());
+            params.push(Box::new(id.to_owned()));
+        }
+    }
+    if term.field == Field::Tag {
+        // Without wildcards, tags are matched exactly, never by part.
+        if term.wildcard {
+            conditions.push("ip IN (SELECT ip FROM entry_tags WHERE tag LIKE ? ESCAPE '\\')".to_owned());
+            params.push(Box::new(pattern.clone()));
+        } else {
+            conditions.push("ip IN (SELECT ip FROM entry_tags WHERE tag = ?)".to_owned());
+            params.push(Box::new(q.to_owned()));
+        }
+    }
+    if conditions.is_empty() {
+        "0".to_owned()
+    } else {
+        conditions.join(" OR ")
+    }
+}
+
+impl Store for SqliteStore {
+    fn add(&self, entry: &Entry) -> Result<()> {
+        let mut conn = self.lock()?;
+        let tx = conn.transaction()?;
+        insert(&tx, entry)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn create(&self, entry: &Entry) -> Result<Entry> {
+        let mut conn = self.lock()?;
+        let tx = conn.transaction()?;
+        let current = current(&tx, &entry.ip.to_string())?;
+        let saved = first_revision(current.as_ref(), entry)?;
+        insert(&tx, &saved)?;
+        tx.commit()?;
+        Ok(saved)
+    }
+
+    fn update(&self, entry: &Entry, revision: u64) -> Result<Entry> {
+        let mut conn = self.lock()?;
+        let tx = conn.transaction()?;
+        let current = current(&tx, &entry.ip.to_string())?;
+        let saved = next_revision(current.as_ref(), entry, revision)?;
+        insert(&tx, &saved)?;
+        tx.commit()?;
+        Ok(saved)
+    }
+
+    fn get(&self, ip: &str) -> Option<Entry> {
+        self.select("SELECT data FROM entries WHERE ip = ?1", &[&ip])
+            .ok()
+            .and_then(|entries| entries.into_iter().next())
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        self.select("SELECT data FROM entries ORDER BY ip_key", &[])
+            .unwrap_or_default()
+    }
+
+    fn delete(&self, ip: &str, user: &User) -> Result<Entry> {
+        let mut conn = self.lock()?;
+        let tx = conn.transaction()?;
+        let entry = match current(&tx, ip)? {
+            Some(e) => e,
+            None => bail!(ErrorKind::EntryNotFound(ip.to_owned())),
+        };
+        let trashed = Trashed::new(entry.clone(), user);
+        remove(&tx, ip)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO trash (ip, deleted_at, data) VALUES (?1, ?2, ?3)",
+            &[
+                &ip,
+                &trashed.deleted_at.timestamp(),
+                &toml::to_string(&trashed)?,
+            ],
+        )?;
+        tx.commit()?;
+        Ok(entry)
+    }
+
+    fn trash(&self) -> Vec<Trashed> {
+        self.select_trash("SELECT data FROM trash ORDER BY deleted_at DESC", &[])
+            .unwrap_or_default()
+    }
+
+    fn restore(&self, ip: &str) -> Result<Option<Entry>> {
+        let trashed = self.select_trash("SELECT data FROM trash WHERE ip = ?1", &[&ip])?
+            .into_iter()
+            .next();
+        let entry = match trashed {
+            Some(t) => t.entry,
+            None => return Ok(None),
+        };
+        let mut conn = self.lock()?;
+        let tx = conn.transaction()?;
+        let exists: i64 = tx.query_row(
+            "SELECT COUNT(*) FROM entries WHERE ip = ?1",
+            &[&ip],
+            |row| row.get(0),
+        )?;
+        if exists > 0 {
+            bail!(ErrorKind::EntryExists(ip.to_owned()));
+        }
+        insert(&tx, &entry)?;
+        tx.execute("DELETE FROM trash WHERE ip = ?1", &[&ip])?;
+        tx.commit()?;
+        Ok(Some(entry))
+    }
+
+    fn purge(&self, before: DateTime<Utc>) -> Result<usize> {
+        let conn = self.lock()?;
+        Ok(conn.execute(
+            "DELETE FROM trash WHERE deleted_at < ?1",
+            &[&before.timestamp()],
+        )?)
+    }
+
+    fn list(&self, query: &Expr, order: &Order) -> Vec<Query> {
+        // Narrow the candidates down with the indexed columns, then let
+        // `ip::list` apply the exact matching rules.
+        let mut params: Vec<Box<ToSql>> = vec![];
+        let sql = format!(
+            "SELECT data FROM entries WHERE {}",
+            condition(query, &mut params)
+        );
+        let params: Vec<&ToSql> = params.iter().map(|p| &**p).collect();
+
+        ip::list(query, order, &self.select(&sql, &params).unwrap_or_default())
+    }
+
+    fn issue(&self, required_ports: &[PortSpec]) -> Option<Entry> {
+        let mut ports = required_ports.to_vec();
+        ports.sort();
+        ports.dedup();
+        let ports: Vec<(String, i64, i64)> = ports
+            .iter()
+            .map(|p| (p.protocol.as_str().to_owned(), i64::from(p.start), i64::from(p.end)))
+            .collect();
+
+        // Expired leases are only reclaimed when no available entry fits.
+        let today = ip::today().to_string();
+        let mut sql = "SELECT data FROM entries \
+                       WHERE (status = 'available' OR (status = 'in-use' AND lease_expires < ?))"
+            .to_owned();
+        let mut params: Vec<&ToSql> = vec![&today];
+        for &(ref protocol, ref start, ref end) in &ports {
+            sql.push_str(&format!(
+                " AND ip IN (SELECT ip FROM entry_port_ranges WHERE {})",
+                PORT_MATCHES
+            ));
+            params.push(protocol);
+            params.push(protocol);
+            params.push(start);
+            params.push(end);
+        }
+        sql.push_str(" ORDER BY status = 'in-use', ip_key LIMIT 1");
+
+        self.select(&sql, &params)
+            .ok()
+            .and_then(|entries| entries.into_iter().next())
+    }
+
+    fn with_mac(&self, mac: &MacAddr) -> Option<Entry> {
+        self.select(
+            "SELECT data FROM entries WHERE ip IN (SELECT ip FROM entry_macs WHERE mac = ?1)",
+            &[&mac.to_string()],
+        ).ok()
+            .and_then(|entries| entries.into_iter().next())
+    }
+
+    fn migrate(&self) -> Result<usize> {
+        let rows = {
+            let conn = self.lock()?;
+            let mut stmt = conn.prepare("SELECT ip, data FROM entries")?;
+            let rows = stmt.query_map(&[], |row| {
+                (row.get::<_, String>(0), row.get::<_, String>(1))
+            })?;
+            let mut data = vec![];
+            for row in rows {
+                data.push(row?);
+            }
+            data
+        };
+        let mut conn = self.lock()?;
+        let tx = conn.transaction()?;
+        let mut migrated = 0;
+        for (ip, data) in rows {
+            // Damaged rows are left alone for `fsck` to report.
+            let entry = match Entry::from_toml(&data) {
+                Ok(e) => e,
+                Err(_) => continue,
+            };
+            let key = entry.ip.to_string();
+            if key != ip {
+                // Rows written before addresses were normalized are moved to
+                // the canonical key, unless that would overwrite another row.
+                let exists: i64 = tx.query_row(
+                    "SELECT COUNT(*) FROM entries WHERE ip = ?1",
+                    &[&key],
+                    |row| row.get(0),
+                )?;
+                if exists > 0 {
+                    continue;
+                }
+                remove(&tx, &ip)?;
+            }
+            insert(&tx, &entry)?;
+            migrated += 1;
+        }
+        tx.commit()?;
+        Ok(migrated)
+    }
+
+    fn record(&self, event: &Event) -> Result<()> {
+        let conn = self.lock()?;
+        conn.execute(
+            "INSERT INTO history (ip, data) VALUES (?1, ?2)",
+            &[&event.ip, &serde_json::to_string(event)?],
+        )?;
+        Ok(())
+    }
+
+    fn history(&self, ip: &str) -> Vec<Event> {
+        let conn = match self.lock() {
+            Ok(c) => c,
+            Err(_) => return vec![],
+        };
+        let mut stmt = match conn.prepare("SELECT data FROM history WHERE ip = ?1 ORDER BY id") {
+            Ok(s) => s,
+            Err(_) => return vec![],
+        };
+        let rows = match stmt.query_map(&[&ip], |row| row.get::<_, String>(0)) {
+            Ok(r) => r,
+            Err(_) => return vec![],
+        };
+        rows.filter_map(|data| data.ok())
+            .filter_map(|data| serde_json::from_str(&data).ok())
+            .collect()
+    }
+
+    fn fsck(&self, quarantine: bool) -> Result<FsckReport> {
+        let mut conn = self.lock()?;
+        let mut problems = vec![];
+        let checked;
+        {
+            let mut stmt = conn.prepare("SELECT ip, data FROM entries")?;
+            let rows = stmt.query_map(&[], |row| {
+                (row.get::<_, String>(0), row.get::<_, String>(1))
+            })?;
+            let mut count = 0;
+            for row in rows {
+                let (ip, data) = row?;
+                count += 1;
+                match Entry::from_toml(&data) {
+                    Ok(entry) => if entry.ip.to_string() != ip {
+                        problems.push(Problem::Mismatch(ip, entry.ip.to_string()));
+                    },
+                    Err(e) => problems.push(Problem::Invalid(ip, e.to_string())),
+                }
+            }
+            checked = count;
+        }
+
+        let mut quarantined = vec![];
+        if quarantine {
+            let tx = conn.transaction()?;
+            for ip in problems.iter().filter_map(|p| p.quarantinable()) {
+                tx.execute(
+                    "INSERT INTO quarantine (ip, data) SELECT ip, data FROM entries WHERE ip = ?1",
+                    &[&ip],
+                )?;
+                remove(&tx, &ip)?;
+                quarantined.push(ip.to_owned());
+            }
+            tx.commit()?;
+        }
+
+        Ok(FsckReport {
+            checked,
+            problems,
+            quarantined,
+        })
+    }
+}
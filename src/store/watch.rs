@@ -0,0 +1,197 @@
+extern crate chrono;
+extern crate notify;
+
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+
+use self::chrono::{DateTime, Utc};
+use self::notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+
+use super::{FileStore, FsckReport, Store, Trashed};
+use super::file::file_ip;
+use super::index::Index;
+use super::super::error::{ErrorKind, Result};
+use super::super::history::Event;
+use super::super::ip::{Entry, Query};
+use super::super::mac::MacAddr;
+use super::super::port::PortSpec;
+use super::super::query::Expr;
+use super::super::slack::User;
+use super::super::sort::Order;
+
+/// Serves reads of a file based store from an in-memory `Index`. The data
+/// folder is watched, so files edited outside the server are picked up too.
+pub struct WatchedStore {
+    inner: Box<Store>,
+    index: Arc<RwLock<Index>>,
+    _watcher: Mutex<RecommendedWatcher>,
+}
+
+impl WatchedStore {
+    pub fn open(inner: Box<Store>, data_path: &str) -> Result<WatchedStore> {
+        let index = Arc::new(RwLock::new(Index::new(inner.entries())));
+
+        let (tx, rx) = channel();
+        let mut watcher: RecommendedWatcher = Watcher::new(tx, Duration::from_millis(200))?;
+        watcher.watch(data_path, RecursiveMode::NonRecursive)?;
+
+        let files = FileStore::new(data_path);
+        let watched_index = index.clone();
+        thread::spawn(move || {
+            for event in rx {
+                match event {
+                    DebouncedEvent::Create(ref p)
+                    | DebouncedEvent::Write(ref p)
+                    | DebouncedEvent::Remove(ref p) => refresh(&watched_index, &files, p),
+                    DebouncedEvent::Rename(ref from, ref to) => {
+                        refresh(&watched_index, &files, from);
+                        refresh(&watched_index, &files, to);
+                    }
+                    DebouncedEvent::Rescan => if let Ok(mut index) = watched_index.write() {
+                        *index = Index::new(files.entries());
+                    },
+                    _ => {}
+                }
+            }
+        });
+
+        Ok(WatchedStore {
+            inner,
+            index,
+            _watcher: Mutex::new(watcher),
+        })
+    }
+
+    fn update_index<F: FnOnce(&mut Index)>(&self, f: F) -> Result<()> {
+        let mut index = self.index
+            .write()
+            .map_err(|_| ErrorKind::Poisoned("index"))?;
+        f(&mut index);
+        Ok(())
+    }
+}
+
+/// Reloads the entry stored in the file at `path`, or drops it from the index
+/// when the file is gone.
+fn refresh(index: &RwLock<Index>, files: &FileStore, path: &Path) {
+    let ip = match path.file_name().and_then(|n| file_ip(&n.to_string_lossy())) {
+        Some(ip) => ip,
+        None => return,
+    };
+    if let Ok(mut index) = index.write() {
+        match files.get(&ip) {
+            Some(entry) => index.insert(entry),
+            None => {
+                index.remove(&ip);
+            }
+        }
+    }
+}
+
+impl Store for WatchedStore {
+    fn add(&self, entry: &Entry) -> Result<()> {
+        self.inner.add(entry)?;
+        self.update_index(|index| index.insert(entry.clone()))
+    }
+
+    fn create(&self, entry: &Entry) -> Result<Entry> {
+        let saved = self.inner.create(entry)?;
+        self.update_index(|index| index.insert(saved.clone()))?;
+        Ok(saved)
+    }
+
+    fn update(&self, entry: &Entry, revision: u64) -> Result<Entry> {
+        let saved = self.inner.update(entry, revision)?;
+        self.update_index(|index| index.insert(saved.clone()))?;
+        Ok(saved)
+    }
+
+    fn get(&self, ip: &str) -> Option<Entry> {
+        self.index
+            .read()
+            .ok()
+            .and_then(|index| index.get(ip).cloned())
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        // The index keeps them in IP order already.
+        self.index
+            .read()
+            .map(|index| index.entries().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn delete(&self, ip: &str, user: &User) -> Result<Entry> {
+        let entry = self.inner.delete(ip, user)?;
+        self.update_index(|index| {
+            index.remove(ip);
+        })?;
+        Ok(entry)
+    }
+
+    fn trash(&self) -> Vec<Trashed> {
+        self.inner.trash()
+    }
+
+    fn restore(&self, ip: &str) -> Result<Option<Entry>> {
+        let entry = self.inner.restore(ip)?;
+        if let Some(ref entry) = entry {
+            self.update_index(|index| index.insert(entry.clone()))?;
+        }
+        Ok(entry)
+    }
+
+    fn purge(&self, before: DateTime<Utc>) -> Result<usize> {
+        self.inner.purge(before)
+    }
+
+    fn record(&self, event: &Event) -> Result<()> {
+        self.inner.record(event)
+    }
+
+    fn history(&self, ip: &str) -> Vec<Event> {
+        self.inner.history(ip)
+    }
+
+    fn list(&self, query: &Expr, order: &Order) -> Vec<Query> {
+        self.index
+            .read()
+            .map(|index| index.list(query, order))
+            .unwrap_or_default()
+    }
+
+    fn issue(&self, required_ports: &[PortSpec]) -> Option<Entry> {
+        self.index
+            .read()
+            .ok()
+            .and_then(|index| index.issue(required_ports))
+    }
+
+    fn with_mac(&self, mac: &MacAddr) -> Option<Entry> {
+        self.index
+            .read()
+            .ok()
+            .and_then(|index| index.with_mac(mac).cloned())
+    }
+
+    fn migrate(&self) -> Result<usize> {
+        let migrated = self.inner.migrate()?;
+        let entries = self.inner.entries();
+        self.update_index(|index| *index = Index::new(entries))?;
+        Ok(migrated)
+    }
+
+    fn fsck(&self, quarantine: bool) -> Result<FsckReport> {
+        let report = self.inner.fsck(quarantine)?;
+        let entries = self.inner.entries();
+        self.update_index(|index| *index = Index::new(entries))?;
+        Ok(report)
+    }
+
+    fn revision(&self, ip: &str, rev: Option<&str>) -> Result<Option<Entry>> {
+        self.inner.revision(ip, rev)
+    }
+}
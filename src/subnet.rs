@@ -0,0 +1,353 @@
+//! Subnets defined in the settings file.
+//!
+//! Entries don't store which subnet they belong to; it's looked up from the
+//! address whenever it's needed, so editing the settings re-attributes them.
+
+extern crate serde;
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use self::serde::de::{Deserialize, Deserializer, Error};
+
+use super::ip::Entry;
+use super::status::Status;
+
+/// A network in CIDR notation, like `10.0.0.0/24` or `2001:db8::/32`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cidr {
+    pub addr: IpAddr,
+    pub prefix: u8,
+}
+
+impl Cidr {
+    fn bits(&self) -> u8 {
+        match self.addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        }
+    }
+
+    fn mask(&self) -> u128 {
+        let host_bits = u32::from(self.bits() - self.prefix);
+        (!0u128).checked_shl(host_bits).unwrap_or(0)
+    }
+
+    /// Whether `ip` is inside this network. Addresses of the other family
+    /// never are.
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, *ip) {
+            (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => {
+                to_bits(&self.addr) & self.mask() == to_bits(ip) & self.mask()
+            }
+            _ => false,
+        }
+    }
+
+    /// The number of addresses in the network, saturating for `::/0`.
+    pub fn size(&self) -> u128 {
+        1u128
+            .checked_shl(u32::from(self.bits() - self.prefix))
+            .unwrap_or(u128::max_value())
+    }
+
+    /// Every address of the network, from the network address to the
+    /// broadcast address.
+    pub fn range(&self) -> IpRange {
+        let network = to_bits(&self.addr) & self.mask();
+        let host = match self.addr {
+            IpAddr::V4(_) => !self.mask() & u128::from(u32::max_value()),
+            IpAddr::V6(_) => !self.mask(),
+        };
+        IpRange {
+            start: from_bits(network, self.addr.is_ipv6()),
+            end: from_bits(network | host, self.addr.is_ipv6()),
+        }
+    }
+
+    /// The network and broadcast addresses of an IPv4 network, which can't be
+    /// handed out. Networks of `/31` and `/32` have none.
+    pub fn unusable(&self) -> Vec<IpAddr> {
+        match self.addr {
+            IpAddr::V4(_) if self.prefix < 31 => {
+                let network = to_bits(&self.addr) & self.mask();
+                let broadcast = network | (!self.mask() & u128::from(u32::max_value()));
+                vec![from_bits(network, false), from_bits(broadcast, false)]
+            }
+            _ => vec![],
+        }
+    }
+}
+
+fn to_bits(ip: &IpAddr) -> u128 {
+    match *ip {
+        IpAddr::V4(ref ip) => u128::from(u32::from(*ip)),
+        IpAddr::V6(ref ip) => u128::from(*ip),
+    }
+}
+
+fn from_bits(bits: u128, v6: bool) -> IpAddr {
+    if v6 {
+        IpAddr::V6(bits.into())
+    } else {
+        IpAddr::V4((bits as u32).into())
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Cidr, String> {
+        let mut parts = s.trim().splitn(2, '/');
+        let addr = parts
+            .next()
+            .and_then(|a| a.parse::<IpAddr>().ok())
+            .ok_or_else(|| format!("Invalid network address: {}", s))?;
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = match parts.next() {
+            Some(p) => p.parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| format!("Invalid prefix length: {}", s))?,
+            None => max,
+        };
+        let cidr = Cidr { addr, prefix };
+        Ok(Cidr {
+            addr: from_bits(to_bits(&addr) & cidr.mask(), addr.is_ipv6()),
+            prefix,
+        })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cidr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Cidr, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+/// An inclusive range of addresses of one family, like `10.0.0.10-10.0.0.50`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct IpRange {
+    pub start: IpAddr,
+    pub end: IpAddr,
+}
+
+impl IpRange {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.start.is_ipv6() == ip.is_ipv6() && to_bits(&self.start) <= to_bits(ip)
+            && to_bits(ip) <= to_bits(&self.end)
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    /// Parses `start-end`. The end of an IPv4 range may leave out the leading
+    /// numbers it shares with the start, as in `10.0.0.10-50`.
+    fn from_str(s: &str) -> Result<IpRange, String> {
+        let invalid = || format!("Invalid address range: {}", s);
+        let mut parts = s.trim().splitn(2, |c| c == '-' || c == '–');
+        let start = parts
+            .next()
+            .and_then(|a| a.parse::<IpAddr>().ok())
+            .ok_or_else(invalid)?;
+        let end = parts.next().ok_or_else(invalid)?;
+        let end = match (start, end.parse::<IpAddr>()) {
+            (_, Ok(end)) => end,
+            (IpAddr::V4(ref start), Err(_)) => {
+                let tail: Vec<&str> = end.split('.').collect();
+                if tail.len() > 3 {
+                    return Err(invalid());
+                }
+                let octets = start.octets();
+                let head = octets[..4 - tail.len()].iter().map(|o| o.to_string());
+                head.chain(tail.iter().map(|t| t.to_string()))
+                    .collect::<Vec<String>>()
+                    .join(".")
+                    .parse()
+                    .map_err(|_| invalid())?
+            }
+            (IpAddr::V6(_), Err(_)) => return Err(invalid()),
+        };
+        if start.is_ipv6() != end.is_ipv6() {
+            return Err(format!("Range of mixed address families: {}", s));
+        }
+        if to_bits(&end) < to_bits(&start) {
+            return Err(format!("Range ends before it starts: {}", s));
+        }
+        Ok(IpRange { start, end })
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+/// A `[[subnets]]` table of the settings file.
+#[derive(Deserialize, Clone)]
+pub struct Subnet {
+    pub name: String,
+    pub cidr: Cidr,
+    pub gateway: Option<IpAddr>,
+    pub vlan: Option<u16>,
+    pub description: Option<String>,
+}
+
+/// Address counts of a subnet.
+pub struct Usage {
+    pub total: u128,
+    pub used: u128,
+    pub reserved: u128,
+    pub free: u128,
+}
+
+impl Subnet {
+    /// Counts the addresses of the subnet. The network, broadcast and gateway
+    /// addresses are reserved, and so are the entries set aside as reserved
+    /// or blocked. Entries in use, deprecated or waiting to be reclaimed are
+    /// used; the rest is free.
+    pub fn usage<'a, I>(&self, entries: I) -> Usage
+    where
+        I: IntoIterator<Item = &'a Entry>,
+    {
+        let mut reserved = self.cidr.unusable();
+        if let Some(gateway) = self.gateway {
+            if self.cidr.contains(&gateway) && !reserved.contains(&gateway) {
+                reserved.push(gateway);
+            }
+        }
+        let mut used = 0;
+        let mut reserved_entries = 0;
+        for e in entries {
+            if !self.cidr.contains(&e.ip) || reserved.contains(&e.ip) {
+                continue;
+            }
+            match e.status {
+                Status::InUse | Status::Deprecated | Status::ReclaimPending => used += 1,
+                Status::Reserved | Status::Blocked => reserved_entries += 1,
+                Status::Available => {}
+            }
+        }
+        let total = self.cidr.size();
+        let reserved = reserved.len() as u128 + reserved_entries;
+        Usage {
+            total,
+            used,
+            reserved,
+            free: total.saturating_sub(used + reserved),
+        }
+    }
+}
+
+/// The most specific subnet containing `ip`.
+pub fn containing<'a>(subnets: &'a [Subnet], ip: &IpAddr) -> Option<&'a Subnet> {
+    subnets
+        .iter()
+        .filter(|s| s.cidr.contains(ip))
+        .max_by_key(|s| s.cidr.prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn range(s: &str) -> Result<(String, String), String> {
+        s.parse::<IpRange>()
+            .map(|r| (r.start.to_string(), r.end.to_string()))
+    }
+
+    #[test]
+    fn parse_cidr() {
+        let cidr: Cidr = "10.0.0.0/24".parse().unwrap();
+        assert_eq!(cidr.addr, ip("10.0.0.0"));
+        assert_eq!(cidr.prefix, 24);
+        assert_eq!(cidr.to_string(), "10.0.0.0/24");
+        // Host bits are cleared.
+        assert_eq!("10.0.0.77/24".parse::<Cidr>().unwrap().to_string(), "10.0.0.0/24");
+        assert_eq!("2001:db8::1/32".parse::<Cidr>().unwrap().to_string(), "2001:db8::/32");
+        // A single address is a network of its own.
+        assert_eq!("10.0.0.1".parse::<Cidr>().unwrap().prefix, 32);
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/24".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn cidr_contains() {
+        let cidr: Cidr = "10.0.0.0/30".parse().unwrap();
+        assert!(cidr.contains(&ip("10.0.0.0")));
+        assert!(cidr.contains(&ip("10.0.0.3")));
+        assert!(!cidr.contains(&ip("10.0.0.4")));
+        assert!(!cidr.contains(&ip("::ffff:10.0.0.1")));
+        let all: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(all.contains(&ip("192.168.0.1")));
+        assert!(!all.contains(&ip("::1")));
+    }
+
+    #[test]
+    fn cidr_size_and_range() {
+        let cidr: Cidr = "10.0.0.0/24".parse().unwrap();
+        assert_eq!(cidr.size(), 256);
+        assert_eq!(cidr.range().to_string(), "10.0.0.0-10.0.0.255");
+        assert_eq!(cidr.unusable(), vec![ip("10.0.0.0"), ip("10.0.0.255")]);
+        assert!("10.0.0.0/31".parse::<Cidr>().unwrap().unusable().is_empty());
+        let v6: Cidr = "2001:db8::/126".parse().unwrap();
+        assert_eq!(v6.size(), 4);
+        assert_eq!(v6.range().to_string(), "2001:db8::-2001:db8::3");
+        assert!(v6.unusable().is_empty());
+        assert_eq!("::/0".parse::<Cidr>().unwrap().size(), u128::max_value());
+    }
+
+    #[test]
+    fn parse_range() {
+        assert_eq!(
+            range("10.0.0.10-10.0.0.50"),
+            Ok(("10.0.0.10".to_owned(), "10.0.0.50".to_owned()))
+        );
+        assert_eq!(
+            range("10.0.0.10-50"),
+            Ok(("10.0.0.10".to_owned(), "10.0.0.50".to_owned()))
+        );
+        assert_eq!(
+            range("10.0.0.10–1.20"),
+            Ok(("10.0.0.10".to_owned(), "10.0.1.20".to_owned()))
+        );
+        assert_eq!(
+            range("2001:db8::1-2001:db8::ff"),
+            Ok(("2001:db8::1".to_owned(), "2001:db8::ff".to_owned()))
+        );
+        assert!(range("10.0.0.50-10").is_err());
+        assert!(range("10.0.0.10-1.2.3.4.5").is_err());
+        assert!(range("10.0.0.10-300").is_err());
+        assert!(range("10.0.0.10").is_err());
+        assert!(range("2001:db8::1-ff").is_err());
+        assert!(range("10.0.0.1-::1").is_err());
+    }
+
+    #[test]
+    fn range_contains() {
+        let r: IpRange = "10.0.0.10-50".parse().unwrap();
+        assert!(r.contains(&ip("10.0.0.10")));
+        assert!(r.contains(&ip("10.0.0.50")));
+        assert!(!r.contains(&ip("10.0.0.9")));
+        assert!(!r.contains(&ip("10.0.0.100")));
+        assert!(!r.contains(&ip("::a")));
+    }
+}